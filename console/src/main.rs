@@ -0,0 +1,56 @@
+use intcode_computer::ascii::{interact, Console};
+use intcode_computer::device::Host;
+use intcode_computer::loader::load_file;
+use intcode_computer::virtual_machine::{VirtualMachine, VMState};
+use log::{error, info};
+use std::io;
+
+const USAGE: &str = "usage: console <program> [<line>...]";
+
+fn main() {
+    pretty_env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let path = match args.next() {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    let program = match load_file(&path) {
+        Ok(program) => program,
+        Err(err) => {
+            error!("Could not load program: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let vm = match VirtualMachine::new(&program) {
+        Ok(vm) => vm,
+        Err(err) => {
+            error!("Could not start program: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // lines given as arguments are answered before anything is read
+    let mut console = Console::new();
+    for line in args {
+        if let Err(err) = console.send(&line) {
+            error!("{}", err);
+            std::process::exit(2);
+        }
+    }
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut host = Host::new(vm, console);
+    match interact(&mut host, stdin.lock(), stdout.lock()) {
+        Ok(VMState::Halted) => info!("Program halted"),
+        Ok(_) => info!("Input ended while the program waits for more"),
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
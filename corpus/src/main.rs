@@ -0,0 +1,83 @@
+use pretty_env_logger;
+use log::{info, error};
+use runfiles::Runfiles;
+use intcode_computer::compiler;
+use intcode_computer::container::Container;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/// Containers given on the command line or, without arguments, every
+/// program in the bundled corpus.
+fn containers() -> Vec<PathBuf> {
+    let args: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if !args.is_empty() {
+        return args;
+    }
+    let r = Runfiles::create().unwrap();
+    let dir = r.rlocation("aoc_solutions/corpus/programs");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Loads a container. A `.ic` file is a program for `compiler` instead,
+/// optionally behind a container header, and is compiled first.
+fn load(path: &Path) -> Result<Container, String> {
+    if path.extension() != Some(OsStr::new("ic")) {
+        return Container::load_file(path).map_err(|err| err.to_string());
+    }
+    let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let (mut header, mut source) = (String::new(), String::new());
+    for line in text.lines() {
+        // blank lines in place of the header keep the line numbers of errors
+        if line.starts_with("#!") {
+            header.push_str(line);
+            header.push('\n');
+            source.push('\n');
+        } else {
+            source.push_str(line);
+            source.push('\n');
+        }
+    }
+    let image = compiler::compile(&source).map_err(|err| err.to_string())?;
+    let program: Vec<String> = image.program.iter().map(|value| value.to_string()).collect();
+    header.push_str(&program.join(","));
+    Container::load(header.as_bytes()).map_err(|err| err.to_string())
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let mut failed = 0;
+    let mut passed = 0;
+    for path in containers() {
+        let container = match load(&path) {
+            Ok(container) => container,
+            Err(err) => {
+                error!("Could not load {}: {}", path.display(), err);
+                failed += 1;
+                continue;
+            }
+        };
+        let name = container.metadata.name.clone().unwrap_or_else(|| path.display().to_string());
+        info!("Running {} tests of {}", container.metadata.tests.len(), name);
+
+        for (i, result) in container.run_tests().into_iter().enumerate() {
+            match result {
+                Ok(()) => passed += 1,
+                Err(err) => {
+                    println!("FAIL {} test {}: {}", name, i + 1, err);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        exit(1);
+    }
+}
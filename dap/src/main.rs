@@ -0,0 +1,73 @@
+mod server;
+
+use log::{error, info};
+use protocol::{read_message, write_message};
+use server::Server;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+/// Serves one client. Requests are read on a separate thread so that they
+/// can be handled while the program is running. A message that can not be
+/// read is answered with an error and the next one is read.
+fn serve<W: Write>(mut writer: W) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+        loop {
+            match read_message(&mut reader) {
+                Ok(Some(message)) => if sender.send(Ok(message)).is_err() {
+                    break;
+                },
+                Ok(None) => break,
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    error!("Could not read message: {}", err);
+                    if sender.send(Err(err.to_string())).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    error!("Could not read message: {}", err);
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut server = Server::new();
+    while !server.is_finished() {
+        let received = if server.is_running() {
+            match receiver.try_recv() {
+                Ok(message) => Some(message),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            }
+        };
+        let messages = match received {
+            Some(Ok(request)) => server.handle(&request),
+            Some(Err(reason)) => server.reject(&reason),
+            None => server.poll(),
+        };
+        for message in &messages {
+            write_message(&mut writer, message)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    info!("Serving the Debug Adapter Protocol on stdio");
+    let stdout = io::stdout();
+    if let Err(err) = serve(stdout.lock()) {
+        error!("Connection failed: {}", err);
+        std::process::exit(1);
+    }
+}
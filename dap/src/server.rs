@@ -0,0 +1,637 @@
+//! Request handling of the debug adapter.
+//!
+//! Memory references are word addresses written as decimal numbers. Memory
+//! is read as 8 little endian bytes per word, so byte offsets and counts of
+//! `readMemory` are rounded to whole words.
+
+use intcode_computer::compiler;
+use intcode_computer::debugger::{Debugger, Mode, Stop};
+use intcode_computer::loader;
+use intcode_computer::memory::MemoryValueType;
+use intcode_computer::sourcemap::{DebugInfo, Location};
+use log::debug;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Instructions executed between checks for new requests.
+pub const CHUNK: usize = 10_000;
+
+const THREAD: i64 = 1;
+const REGISTERS: i64 = 1;
+const WORD: usize = std::mem::size_of::<MemoryValueType>();
+/// Most bytes a `readMemory` response holds, larger requests are cut short.
+const MAX_READ: usize = 64 * 1024;
+
+type Response = Result<Value, String>;
+
+pub struct Server {
+    seq: i64,
+    debugger: Option<Debugger>,
+    /// Source of a program compiled at launch, it is the module `main`
+    source: Option<PathBuf>,
+    stop_on_entry: bool,
+    line_breakpoints: BTreeMap<String, Vec<usize>>,
+    instruction_breakpoints: Vec<usize>,
+    events: Vec<Value>,
+    finished: bool,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            seq: 0,
+            debugger: None,
+            source: None,
+            stop_on_entry: false,
+            line_breakpoints: BTreeMap::new(),
+            instruction_breakpoints: Vec::new(),
+            events: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// True while the program runs and `poll` has to be called.
+    pub fn is_running(&self) -> bool {
+        self.debugger.as_ref().map_or(false, Debugger::is_running)
+    }
+
+    /// True after the client disconnected.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Handles a request and returns the response followed by any events.
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or("");
+        let arguments = &request["arguments"];
+        debug!("Request `{}`: {}", command, arguments);
+        let body = match command {
+            "initialize" => Ok(capabilities()),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({"threads": [{"id": THREAD, "name": "intcode"}]})),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({"scopes": [
+                {"name": "Registers", "variablesReference": REGISTERS, "expensive": false}
+            ]})),
+            "variables" => self.variables(arguments),
+            "continue" => self.resume(Mode::Continue).map(|_| json!({"allThreadsContinued": true})),
+            "next" => self.resume(step_mode(arguments, Mode::StepOver)),
+            "stepIn" => self.resume(step_mode(arguments, Mode::StepIn)),
+            "pause" => self.pause(),
+            "readMemory" => self.read_memory(arguments),
+            "evaluate" => self.evaluate(arguments),
+            "disconnect" => {
+                self.finished = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("Unsupported request `{}`", command)),
+        };
+
+        let mut response = json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(Value::Null) => (),
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::String(message),
+        }
+        if command == "initialize" {
+            self.event("initialized", Value::Null);
+        }
+
+        let mut messages = vec![response];
+        messages.append(&mut self.events);
+        messages
+    }
+
+    /// Answers a message that could not be read with a failed response.
+    pub fn reject(&mut self, reason: &str) -> Vec<Value> {
+        vec![json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": 0,
+            "command": "",
+            "success": false,
+            "message": format!("Malformed message: {}", reason),
+        })]
+    }
+
+    /// Runs the program for a while and returns the resulting events.
+    pub fn poll(&mut self) -> Vec<Value> {
+        let result = match &mut self.debugger {
+            Some(debugger) => debugger.run(CHUNK),
+            None => return Vec::new(),
+        };
+        self.flush_outputs();
+        match result {
+            Ok(Some(stop)) => self.stopped(stop),
+            Ok(None) => (),
+            Err(err) => self.event("stopped", json!({
+                "reason": "exception",
+                "description": err.to_string(),
+                "threadId": THREAD,
+            })),
+        }
+        std::mem::take(&mut self.events)
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        let mut message = json!({"seq": self.next_seq(), "type": "event", "event": event});
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.events.push(message);
+    }
+
+    fn debugger(&self) -> Result<&Debugger, String> {
+        self.debugger.as_ref().ok_or_else(|| "No program has been launched".to_string())
+    }
+
+    fn debugger_mut(&mut self) -> Result<&mut Debugger, String> {
+        self.debugger.as_mut().ok_or_else(|| "No program has been launched".to_string())
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Response {
+        let (program, info) = if let Some(source) = arguments["source"].as_str() {
+            let text = std::fs::read_to_string(source).map_err(|err| format!("Could not read {}: {}", source, err))?;
+            let image = compiler::compile(&text).map_err(|err| format!("Could not compile {}: {}", source, err))?;
+            self.source = Some(PathBuf::from(source));
+            let info = image.debug_info();
+            (image.program, info)
+        } else if let Some(path) = arguments["program"].as_str() {
+            let program = loader::load_file(path).map_err(|err| format!("Could not load {}: {}", path, err))?;
+            let info = match arguments["debugInfo"].as_str() {
+                Some(path) => std::fs::read_to_string(path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|text| DebugInfo::parse(&text).map_err(|err| err.to_string()))
+                    .map_err(|err| format!("Could not load debug info {}: {}", path, err))?,
+                None => DebugInfo::default(),
+            };
+            (program, info)
+        } else {
+            return Err("Either `program` or `source` is required".to_string());
+        };
+
+        let mut debugger = Debugger::new(&program, info).map_err(|err| err.to_string())?;
+        for input in arguments["input"].as_array().into_iter().flatten() {
+            debugger.provide_input(input.as_i64().ok_or("Inputs have to be integers")?);
+        }
+        self.debugger = Some(debugger);
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.sync_breakpoints();
+        Ok(Value::Null)
+    }
+
+    /// Module name of a DAP source.
+    fn module(&self, source: &Value) -> String {
+        let path = source["path"].as_str().map(Path::new);
+        if path.is_some() && path == self.source.as_deref() {
+            return "main".to_string();
+        }
+        path.and_then(Path::file_stem)
+            .and_then(|stem| stem.to_str())
+            .or_else(|| source["name"].as_str())
+            .unwrap_or("")
+            .to_string()
+    }
+
+    fn source(&self, location: &Location) -> Value {
+        match &self.source {
+            Some(path) if location.file == "main" => json!({
+                "name": path.file_name().and_then(|name| name.to_str()).unwrap_or("main"),
+                "path": path.to_str(),
+            }),
+            _ => json!({"name": location.file}),
+        }
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Response {
+        let module = self.module(&arguments["source"]);
+        let lines: Vec<usize> = match arguments["breakpoints"].as_array() {
+            Some(breakpoints) => breakpoints.iter().filter_map(|b| b["line"].as_u64()).map(|l| l as usize).collect(),
+            None => arguments["lines"].as_array().into_iter().flatten()
+                .filter_map(Value::as_u64)
+                .map(|l| l as usize)
+                .collect(),
+        };
+
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            let ranges = match &self.debugger {
+                Some(debugger) => debugger.debug_info().source.addresses(&module, line),
+                None => Vec::new(),
+            };
+            addresses.extend(ranges.iter().map(|range| range.start));
+            breakpoints.push(json!({
+                "verified": !ranges.is_empty(),
+                "line": line,
+                "instructionReference": ranges.first().map(|range| range.start.to_string()),
+            }));
+        }
+        self.line_breakpoints.insert(module, addresses);
+        self.sync_breakpoints();
+        Ok(json!({"breakpoints": breakpoints}))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Response {
+        let mut breakpoints = Vec::new();
+        self.instruction_breakpoints.clear();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let address = breakpoint["instructionReference"].as_str()
+                .and_then(|reference| reference.parse::<i64>().ok())
+                .map(|address| address + breakpoint["offset"].as_i64().unwrap_or(0))
+                .filter(|&address| address >= 0);
+            if let Some(address) = address {
+                self.instruction_breakpoints.push(address as usize);
+            }
+            breakpoints.push(json!({"verified": address.is_some()}));
+        }
+        self.sync_breakpoints();
+        Ok(json!({"breakpoints": breakpoints}))
+    }
+
+    fn sync_breakpoints(&mut self) {
+        let addresses: Vec<usize> = self.line_breakpoints.values()
+            .flatten()
+            .chain(&self.instruction_breakpoints)
+            .cloned()
+            .collect();
+        if let Some(debugger) = &mut self.debugger {
+            debugger.set_breakpoints(addresses);
+        }
+    }
+
+    fn configuration_done(&mut self) -> Response {
+        if self.stop_on_entry {
+            self.debugger()?;
+            self.stopped(Stop::Step);
+            if let Some(event) = self.events.last_mut() {
+                event["body"]["reason"] = json!("entry");
+            }
+        } else {
+            self.debugger_mut()?.start(Mode::Continue);
+        }
+        Ok(Value::Null)
+    }
+
+    fn resume(&mut self, mode: Mode) -> Response {
+        self.debugger_mut()?.start(mode);
+        Ok(Value::Null)
+    }
+
+    fn pause(&mut self) -> Response {
+        let debugger = self.debugger_mut()?;
+        if debugger.is_running() {
+            let stop = debugger.pause();
+            self.stopped(stop);
+        }
+        Ok(Value::Null)
+    }
+
+    fn stopped(&mut self, stop: Stop) {
+        let (reason, description) = match stop {
+            Stop::Step => ("step", None),
+            Stop::Breakpoint => ("breakpoint", None),
+            Stop::Paused => ("pause", None),
+            Stop::Input => ("pause", Some("Waiting for input, provide it with `input <values>`")),
+            Stop::Halted => {
+                self.event("exited", json!({"exitCode": 0}));
+                self.event("terminated", Value::Null);
+                return;
+            }
+        };
+        self.event("stopped", json!({
+            "reason": reason,
+            "description": description,
+            "threadId": THREAD,
+            "allThreadsStopped": true,
+        }));
+    }
+
+    fn flush_outputs(&mut self) {
+        let outputs = match &mut self.debugger {
+            Some(debugger) => debugger.take_outputs(),
+            None => return,
+        };
+        if !outputs.is_empty() {
+            let text: String = outputs.iter().map(|value| format!("{}\n", value)).collect();
+            self.event("output", json!({"category": "stdout", "output": text}));
+        }
+    }
+
+    fn stack_trace(&self) -> Response {
+        let debugger = self.debugger()?;
+        let info = debugger.debug_info();
+        let pc = debugger.vm().pc();
+        let symbol = info.symbols.symbols()
+            .filter(|&(name, at)| at <= pc && !is_generated(name))
+            .max_by_key(|&(name, at)| (at, !name.contains("::")));
+        let name = match symbol {
+            Some((name, at)) if at == pc => name.to_string(),
+            Some((name, at)) => format!("{}+{}", name, pc - at),
+            None => pc.to_string(),
+        };
+        let mut frame = json!({
+            "id": 0,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": pc.to_string(),
+        });
+        if let Some(location) = debugger.location() {
+            frame["source"] = self.source(location);
+            frame["line"] = json!(location.line);
+            frame["column"] = json!(1);
+        }
+        Ok(json!({"stackFrames": [frame], "totalFrames": 1}))
+    }
+
+    fn variables(&self, arguments: &Value) -> Response {
+        if arguments["variablesReference"].as_i64() != Some(REGISTERS) {
+            return Ok(json!({"variables": []}));
+        }
+        let debugger = self.debugger()?;
+        let vm = debugger.vm();
+        let register = |value: Option<MemoryValueType>| value.map_or("-".to_string(), |value| value.to_string());
+        let inputs: Vec<String> = debugger.pending_inputs().map(MemoryValueType::to_string).collect();
+        let variables = vec![
+            ("pc", debugger.debug_info().describe(vm.pc()), Some(vm.pc())),
+            ("relative_base", vm.relative_base().to_string(), Some(vm.relative_base())),
+            ("input", register(vm.input_register()), None),
+            ("output", register(vm.output_register()), None),
+            ("pending_inputs", format!("[{}]", inputs.join(", ")), None),
+            ("state", format!("{:?}", vm.state()), None),
+        ];
+        let variables: Vec<Value> = variables.into_iter()
+            .map(|(name, value, address)| json!({
+                "name": name,
+                "value": value,
+                "variablesReference": 0,
+                "memoryReference": address.map(|address| address.to_string()),
+            }))
+            .collect();
+        Ok(json!({"variables": variables}))
+    }
+
+    fn read_memory(&self, arguments: &Value) -> Response {
+        let debugger = self.debugger()?;
+        let reference = arguments["memoryReference"].as_str().and_then(|reference| reference.parse::<i64>().ok())
+            .ok_or("Invalid memory reference")?;
+        let start = reference.checked_add(arguments["offset"].as_i64().unwrap_or(0) / WORD as i64)
+            .ok_or("Invalid memory reference")?;
+        if start < 0 {
+            return Err("Negative address".to_string());
+        }
+        let start = start as usize;
+        let count = arguments["count"].as_u64().unwrap_or(0).min(MAX_READ as u64) as usize;
+        let end = start.checked_add((count + WORD - 1) / WORD).ok_or("Invalid memory reference")?;
+        let words = debugger.memory(start..end);
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+        Ok(json!({"address": start.to_string(), "data": base64(&bytes)}))
+    }
+
+    /// Evaluates `pc`, `rb`, an address or a symbol, the memory at one of
+    /// these as `[address]`, or queues inputs with `input <values>`.
+    fn evaluate(&mut self, arguments: &Value) -> Response {
+        let expression = arguments["expression"].as_str().unwrap_or("").trim();
+        if expression.starts_with("input") {
+            let values = expression["input".len()..].split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<MemoryValueType>().map_err(|_| format!("Invalid input `{}`", value)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let debugger = self.debugger_mut()?;
+            for &value in &values {
+                debugger.provide_input(value);
+            }
+            return Ok(json!({"result": format!("{} inputs queued", values.len()), "variablesReference": 0}));
+        }
+
+        let debugger = self.debugger()?;
+        let vm = debugger.vm();
+        let address = |s: &str| match s {
+            "pc" => Ok(vm.pc()),
+            "rb" | "relative_base" => Ok(vm.relative_base()),
+            _ => s.parse::<usize>().ok()
+                .or_else(|| debugger.debug_info().symbols.address(s))
+                .ok_or_else(|| format!("Unknown address `{}`", s)),
+        };
+        let (value, reference) = if expression.starts_with('[') && expression.ends_with(']') {
+            (vm[address(expression[1..expression.len() - 1].trim())?], None)
+        } else {
+            let address = address(expression)?;
+            (address as MemoryValueType, Some(address))
+        };
+        Ok(json!({
+            "result": value.to_string(),
+            "variablesReference": 0,
+            "memoryReference": reference.map(|address| address.to_string()),
+        }))
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsReadMemoryRequest": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsSteppingGranularity": true,
+        "supportsEvaluateForHovers": true,
+    })
+}
+
+/// Labels the compiler generates for control flow (`l<n>`) make poor frame
+/// names.
+fn is_generated(symbol: &str) -> bool {
+    let label = symbol.rsplit("::").next().unwrap_or(symbol);
+    label.starts_with('l') && label.len() > 1 && label[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+fn step_mode(arguments: &Value, mode: Mode) -> Mode {
+    if arguments["granularity"].as_str() == Some("instruction") {
+        Mode::Instruction
+    } else {
+        mode
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (i, &byte)| word | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(word >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+fn main() {
+    let n = read();
+    let total = 0;
+    while n > 0 {
+        total = total + n;
+        n = n - 1;
+    }
+    print(total);
+}
+";
+
+    /// Sends the requests one after another and runs the program after each
+    /// until it stops again.
+    fn exchange(server: &mut Server, requests: &[Value]) -> Vec<Value> {
+        let mut messages = Vec::new();
+        for (seq, request) in requests.iter().enumerate() {
+            let mut request = request.clone();
+            request["seq"] = json!(seq + 1);
+            request["type"] = json!("request");
+            messages.extend(server.handle(&request));
+            while server.is_running() {
+                messages.extend(server.poll());
+            }
+        }
+        messages
+    }
+
+    fn events<'a>(messages: &'a [Value], event: &str) -> Vec<&'a Value> {
+        messages.iter().filter(|message| message["event"] == event).collect()
+    }
+
+    fn write(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("dap_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_source_debugging() {
+        let source = write("sum.ic", SOURCE);
+        let mut server = Server::new();
+        let messages = exchange(&mut server, &[
+            json!({"command": "initialize", "arguments": {"adapterID": "intcode"}}),
+            json!({"command": "launch", "arguments": {"source": source, "input": [4]}}),
+            json!({"command": "setBreakpoints", "arguments": {
+                "source": {"path": source}, "breakpoints": [{"line": 5}, {"line": 10}]
+            }}),
+            json!({"command": "configurationDone"}),
+            json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+            json!({"command": "evaluate", "arguments": {"expression": "[rb]"}}),
+            json!({"command": "next", "arguments": {"threadId": 1}}),
+            json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+        ]);
+        assert!(messages.iter().filter(|m| m["type"] == "response").all(|m| m["success"] == true), "{:#?}", messages);
+        assert_eq!(messages[1]["event"], "initialized");
+
+        let breakpoints = &messages[3]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+        assert_eq!(stopped[1]["body"]["reason"], "step");
+
+        let traces: Vec<&Value> = messages.iter().filter(|m| m["command"] == "stackTrace").collect();
+        let frame = &traces[0]["body"]["stackFrames"][0];
+        assert_eq!(frame["line"], 5);
+        assert_eq!(frame["name"].as_str().unwrap().split('+').next(), Some("main::f_main"));
+        assert_eq!(frame["source"]["path"], json!(source));
+        assert_eq!(traces[1]["body"]["stackFrames"][0]["line"], 6);
+
+        // continue through all remaining loop iterations
+        let messages = exchange(&mut server, &[
+            json!({"command": "setBreakpoints", "arguments": {"source": {"path": source}, "breakpoints": []}}),
+            json!({"command": "continue", "arguments": {"threadId": 1}}),
+        ]);
+        assert_eq!(events(&messages, "output")[0]["body"]["output"], "10\n");
+        assert_eq!(events(&messages, "terminated").len(), 1);
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let program = write("echo.int", "3,20,1001,20,1,20,4,20,99");
+        let mut server = Server::new();
+        let messages = exchange(&mut server, &[
+            json!({"command": "launch", "arguments": {"program": program, "stopOnEntry": true}}),
+            json!({"command": "setInstructionBreakpoints", "arguments": {"breakpoints": [{"instructionReference": "6"}]}}),
+            json!({"command": "configurationDone"}),
+            json!({"command": "stepIn", "arguments": {"threadId": 1}}),
+            json!({"command": "evaluate", "arguments": {"expression": "input 41"}}),
+            json!({"command": "continue", "arguments": {"threadId": 1}}),
+            json!({"command": "variables", "arguments": {"variablesReference": REGISTERS}}),
+            json!({"command": "readMemory", "arguments": {"memoryReference": "20", "count": 8}}),
+            json!({"command": "readMemory", "arguments": {"memoryReference": "0", "count": u64::max_value()}}),
+            json!({"command": "readMemory", "arguments": {"memoryReference": i64::max_value().to_string(), "offset": 64}}),
+            json!({"command": "evaluate", "arguments": {"expression": "[20]"}}),
+            json!({"command": "continue", "arguments": {"threadId": 1}}),
+            json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+        ]);
+
+        let stopped = events(&messages, "stopped");
+        let reasons: Vec<&Value> = stopped.iter().map(|event| &event["body"]["reason"]).collect();
+        assert_eq!(reasons, vec!["entry", "pause", "breakpoint"]);
+        assert!(stopped[1]["body"]["description"].as_str().unwrap().contains("input"));
+
+        let variables = &messages.iter().find(|m| m["command"] == "variables").unwrap()["body"]["variables"];
+        assert_eq!(variables[0]["value"], "6");
+        assert_eq!(variables[2]["name"], "input");
+
+        let memory: Vec<&Value> = messages.iter().filter(|m| m["command"] == "readMemory").collect();
+        assert_eq!(memory[0]["body"]["data"], base64(&42i64.to_le_bytes()));
+        assert_eq!(memory[1]["body"]["data"].as_str().unwrap().len(), (MAX_READ + 2) / 3 * 4);
+        assert_eq!(memory[2]["success"], false);
+        let evaluated: Vec<&Value> = messages.iter().filter(|m| m["command"] == "evaluate").collect();
+        assert_eq!(evaluated[1]["body"]["result"], "42");
+
+        assert_eq!(events(&messages, "output")[0]["body"]["output"], "42\n");
+        assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 0);
+        let trace = messages.iter().find(|m| m["command"] == "stackTrace").unwrap();
+        assert_eq!(trace["body"]["stackFrames"][0]["instructionPointerReference"], "8");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut server = Server::new();
+        let messages = exchange(&mut server, &[
+            json!({"command": "threads"}),
+            json!({"command": "stackTrace"}),
+            json!({"command": "launch", "arguments": {}}),
+            json!({"command": "restartFrame"}),
+            json!({"command": "disconnect"}),
+        ]);
+        assert_eq!(messages[0]["success"], true);
+        assert!(messages[1..].iter().take(3).all(|m| m["success"] == false && m["message"].is_string()));
+        assert!(server.is_finished());
+        assert_eq!(server.reject("invalid Content-Length")[0]["success"], false);
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -1,11 +1,15 @@
 use pretty_env_logger;
 use log::{info, error, debug};
 use runfiles::Runfiles;
-use std::fs::read_to_string;
 use intcode_computer::error::*;
-use intcode_computer::util::string_to_program;
+use intcode_computer::loader::load_file;
 use intcode_computer::virtual_machine::VirtualMachine;
 use intcode_computer::memory::MemoryValueType;
+use intcode_computer::symbolic::{SymbolicMachine, Expr, Constraint, solve};
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+
+const TARGET: MemoryValueType = 19690720;
 
 
 fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
@@ -15,21 +19,25 @@ fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
     Ok(vm[0])
 }
 
-fn solution_2(program: &[MemoryValueType]) -> Result<MemoryValueType> {
-    for noun in 0..=99 {
-        for verb in 0..=99 {
-            let mut vm = VirtualMachine::new(program)?;
-            vm[1] = noun;
-            vm[2] = verb;
-            vm.run()?;
-
-            if vm[0] == 19690720 {
-                return Ok(100 * noun + verb)
-            }
+fn solution_2(program: &[MemoryValueType]) -> anyhow::Result<MemoryValueType> {
+    let (noun, verb) = (0, 1);
+    let mut machine = SymbolicMachine::new(program);
+    machine.set_memory(1, Expr::symbol(noun));
+    machine.set_memory(2, Expr::symbol(verb));
+
+    let mut domains = BTreeMap::new();
+    domains.insert(noun, 0..=99);
+    domains.insert(verb, 0..=99);
+
+    for path in machine.explore()? {
+        debug!("Result is {}", path.memory(0));
+        let mut constraints = path.constraints.clone();
+        constraints.push(Constraint::equals(path.memory(0), Expr::constant(TARGET)));
+        if let Some(solution) = solve(&constraints, &domains)? {
+            return Ok(100 * solution[&noun] + solution[&verb])
         }
     }
-    // TODO: lazy
-    unreachable!();
+    Err(anyhow!("No noun and verb produce {}", TARGET))
 }
 
 fn main() {
@@ -38,9 +46,14 @@ fn main() {
     let r = Runfiles::create().unwrap();
     let path = r.rlocation("aoc_solutions/util/input_02");
 
-    let input = read_to_string(path).unwrap();
-    info!("Executing intcode program \"{}\"", input);
-    let mut program = string_to_program(&input);
+    let program = match load_file(path) {
+        Ok(program) => program,
+        Err(err) => {
+            error!("Could not load program: {}", err);
+            return;
+        }
+    };
+    info!("Executing intcode program with {} values", program.len());
 
     match solution_1(&program) {
         Ok(val) => println!("Solution 1: {}", val),
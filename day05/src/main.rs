@@ -1,39 +1,28 @@
 use pretty_env_logger;
 use log::{info, error, debug};
 use runfiles::Runfiles;
-use std::fs::read_to_string;
 use intcode_computer::error::*;
-use intcode_computer::util::string_to_program;
-use intcode_computer::virtual_machine::{VirtualMachine, VMState};
+use intcode_computer::loader::load_file;
+use intcode_computer::outputs::Outputs;
+use intcode_computer::virtual_machine::VirtualMachine;
 use intcode_computer::memory::MemoryValueType;
 
 
-fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
-    let mut vm = VirtualMachine::new(program)?;
-
-    vm.input(1)?;
+/// Runs the diagnostic program for a system and returns its last output,
+/// the diagnostic code.
+fn diagnostics(program: &[MemoryValueType], input: MemoryValueType) -> Result<MemoryValueType> {
+    Outputs::new(VirtualMachine::new(program)?, Some(input))
+        .inspect(|code| if let Ok(code) = code { info!("Got diagnostics code {}", code) })
+        .last()
+        .unwrap_or(Err(VMError::NoOutput))
+}
 
-    let mut code = 0;
-    
-    while (vm.run()? != VMState::Halted) {
-        code = vm.output()?;
-        info!("Got diagnostics code 0");
-    }
-    Ok(code)
+fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
+    diagnostics(program, 1)
 }
 
 fn solution_2(program: &[MemoryValueType]) -> Result<MemoryValueType> {
-    let mut vm = VirtualMachine::new(program)?;
-
-    vm.input(5)?;
-
-    let mut code = 0;
-    
-    while (vm.run()? != VMState::Halted) {
-        code = vm.output()?;
-        info!("Got diagnostics code 0");
-    }
-    Ok(code)
+    diagnostics(program, 5)
 }
 
 fn main() {
@@ -42,9 +31,14 @@ fn main() {
     let r = Runfiles::create().unwrap();
     let path = r.rlocation("aoc_solutions/util/input_05");
 
-    let input = read_to_string(path).unwrap();
-    info!("Executing intcode program \"{}\"", input);
-    let program = string_to_program(&input);
+    let program = match load_file(path) {
+        Ok(program) => program,
+        Err(err) => {
+            error!("Could not load program: {}", err);
+            return;
+        }
+    };
+    info!("Executing intcode program with {} values", program.len());
 
     match solution_1(&program) {
         Ok(val) => println!("Solution 1: {}", val),
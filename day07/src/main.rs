@@ -1,43 +1,32 @@
 use pretty_env_logger;
 use log::{info, error, debug};
 use runfiles::Runfiles;
-use std::fs::read_to_string;
 use intcode_computer::error::*;
-use intcode_computer::util::string_to_program;
-use intcode_computer::virtual_machine::{VirtualMachine, VMState};
+use intcode_computer::loader::load_file;
+use intcode_computer::pipeline::Pipeline;
 use itertools::Itertools;
 
 use intcode_computer::memory::MemoryValueType;
 
-fn run_amplifiers(program: &[MemoryValueType], phase_settings: &[MemoryValueType]) -> Result<MemoryValueType> {
+/// Runs one amplifier per phase setting, each feeding the next one and the
+/// last one feeding back into the first. Every amplifier gets its own thread
+/// in a `Pipeline`, which took over from driving them as futures.
+fn run_amplifiers(program: &[MemoryValueType], phase_settings: &[MemoryValueType]) -> anyhow::Result<MemoryValueType> {
+    let mut pipeline = Pipeline::new(1);
     let mut amplifiers = Vec::with_capacity(phase_settings.len());
-
     for phase_setting in phase_settings {
-        let mut amplifier = VirtualMachine::new(&program)?;
-        amplifier.input(*phase_setting)?;
-        // let machine process first input
-        amplifier.run()?;
-        amplifiers.push(amplifier);
+        amplifiers.push(pipeline.add(program, &[*phase_setting])?);
     }
-
-    let mut input_signal = 0;
-    let mut state = VMState::Running;
-    loop {
-        for amplifier in &mut amplifiers {
-            amplifier.input(input_signal)?;
-            state = amplifier.run()?;
-            if state == VMState::Halted {
-                continue;
-            }
-            input_signal = amplifier.output()?;
-        }
-        if state == VMState::Halted {
-            return Ok(input_signal)
-        }
+    pipeline.feed(amplifiers[0], &[0])?;
+    for (&from, &to) in amplifiers.iter().zip(amplifiers.iter().cycle().skip(1)) {
+        pipeline.connect(from, to)?;
     }
+
+    let last = amplifiers[amplifiers.len() - 1];
+    Ok(pipeline.run()?[last].ok_or(VMError::NoOutput)?)
 }
 
-fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
+fn solution_1(program: &[MemoryValueType]) -> anyhow::Result<MemoryValueType> {
     let mut max = 0;
      
     for phase_settings in (0..=4).permutations(5) {
@@ -50,7 +39,7 @@ fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
     Ok(max)
 }
 
-fn solution_2(program: &[MemoryValueType]) -> Result<MemoryValueType> {
+fn solution_2(program: &[MemoryValueType]) -> anyhow::Result<MemoryValueType> {
     let mut max = 0;
      
     for phase_settings in (5..=9).permutations(5) {
@@ -85,9 +74,14 @@ fn main() {
     let r = Runfiles::create().unwrap();
     let path = r.rlocation("aoc_solutions/util/input_07");
 
-    let input = read_to_string(path).unwrap();
-    info!("Executing intcode program \"{}\"", input);
-    let program = string_to_program(&input);
+    let program = match load_file(path) {
+        Ok(program) => program,
+        Err(err) => {
+            error!("Could not load program: {}", err);
+            return;
+        }
+    };
+    info!("Executing intcode program with {} values", program.len());
 
     match solution_1(&program) {
         Ok(val) => println!("Solution 1: {}", val),
@@ -1,43 +1,29 @@
 use pretty_env_logger;
 use log::{info, error, debug};
 use runfiles::Runfiles;
-use std::fs::read_to_string;
 use intcode_computer::error::*;
-use intcode_computer::util::string_to_program;
+use intcode_computer::loader::load_file;
 use intcode_computer::opcode::Opcode;
 use std::convert::TryFrom;
-use intcode_computer::virtual_machine::{VirtualMachine, VMState};
+use intcode_computer::outputs::Outputs;
+use intcode_computer::virtual_machine::VirtualMachine;
 use intcode_computer::memory::MemoryValueType;
 
 
-fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
-    let mut vm = VirtualMachine::new(program)?;
-
-    vm.input(1)?;
+/// Runs the BOOST program in the given mode and returns its last output.
+fn boost(program: &[MemoryValueType], input: MemoryValueType) -> Result<MemoryValueType> {
+    Outputs::new(VirtualMachine::new(program)?, Some(input))
+        .inspect(|code| if let Ok(code) = code { info!("Got {}", code) })
+        .last()
+        .unwrap_or(Err(VMError::NoOutput))
+}
 
-    let mut code = 0;
-    
-    while (vm.run()? != VMState::Halted) {
-        code = vm.output()?;
-        info!("Got {}", code);
-        
-        //info!("Got Non working op: {}", Opcode::try_from(code as u8)?);
-    }
-    Ok(code)
+fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
+    boost(program, 1)
 }
 
 fn solution_2(program: &[MemoryValueType]) -> Result<MemoryValueType> {
-    let mut vm = VirtualMachine::new(program)?;
-
-    vm.input(2)?;
-
-    let mut code = 0;
-    
-    while (vm.run()? != VMState::Halted) {
-        code = vm.output()?;
-        info!("Got {}", code);
-    }
-    Ok(code)
+    boost(program, 2)
 }
 
 fn main() {
@@ -46,9 +32,14 @@ fn main() {
     let r = Runfiles::create().unwrap();
     let path = r.rlocation("aoc_solutions/util/input_09");
 
-    let input = read_to_string(path).unwrap();
-    info!("Executing intcode program \"{}\"", input);
-    let program = string_to_program(&input);
+    let program = match load_file(path) {
+        Ok(program) => program,
+        Err(err) => {
+            error!("Could not load program: {}", err);
+            return;
+        }
+    };
+    info!("Executing intcode program with {} values", program.len());
 
     match solution_1(&program) {
         Ok(val) => println!("Solution 1: {}", val),
@@ -1,41 +1,43 @@
 use pretty_env_logger;
 use log::{info, error, debug};
 use runfiles::Runfiles;
-use std::fs::read_to_string;
-use intcode_computer::error::*;
-use intcode_computer::util::string_to_program;
+use intcode_computer::loader::load_file;
 use intcode_computer::opcode::Opcode;
-use std::convert::From;
-use intcode_computer::virtual_machine::{VirtualMachine, VMState};
+use intcode_computer::device::{self, Device, Host, InputProvider};
+use intcode_computer::framing::{self, field, Decode, Framer};
+use num_enum::TryFromPrimitive;
+use intcode_computer::virtual_machine::VirtualMachine;
 use intcode_computer::memory::MemoryValueType;
-use std::iter::repeat_with;
+use std::collections::HashMap;
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, TryFromPrimitive)]
+#[repr(i64)]
 enum TileColor {
-    Unchanged,
-    Black,
-    White,
+    Black = 0,
+    White = 1,
 }
 
-impl TileColor {
-    fn val(self) -> MemoryValueType {
-        match self {
-            TileColor::Unchanged => 0,
-            TileColor::Black => 0,
-            TileColor::White => 1,
-        }
-    }
+#[derive(Debug, Eq, PartialEq, Clone, Copy, TryFromPrimitive)]
+#[repr(i64)]
+enum Turn {
+    Left = 0,
+    Right = 1,
 }
 
-impl From<MemoryValueType> for TileColor {
-    fn from(val: MemoryValueType) -> Self {
-        if val == 0 {
-            TileColor::Black
-        } else if val == 1 {
-            TileColor::White
-        } else {
-            panic!("Unkown tilecolor!")
-        }
+/// What the brain tells the robot after every look at the hull.
+struct Command {
+    color: TileColor,
+    turn: Turn,
+}
+
+impl Decode for Command {
+    const SIZE: usize = 2;
+
+    fn decode(values: &[MemoryValueType]) -> framing::Result<Command> {
+        Ok(Command {
+            color: field("color", values[0])?,
+            turn: field("turn", values[1])?,
+        })
     }
 }
 
@@ -46,62 +48,43 @@ enum Direction {
     Down
 }
 
+/// Paints the hull as told by its brain, then turns and moves one panel
+/// ahead.
 struct Robot {
-    brain: VirtualMachine,
+    panels: HashMap<(isize, isize), TileColor>,
     current_direction: Direction,
     current_position: (isize, isize),
-    hull: Vec<Vec<TileColor>>,
+    commands: Framer<Command>,
 }
 
-const INITIAL_SIZE: usize = 200;
-
 impl Robot {
-    fn new(program: &[MemoryValueType]) -> Result<Self> {
-        let hull = repeat_with(|| {
-           let mut v = Vec::new();
-           v.resize(INITIAL_SIZE, TileColor::Unchanged);
-           v
-        }).take(INITIAL_SIZE).collect();
-        Ok(Robot {
-            brain: VirtualMachine::new(program)?,
+    fn new() -> Self {
+        Robot {
+            panels: HashMap::new(),
             current_direction: Direction::Up,
-            current_position: (INITIAL_SIZE / 2, INITIAL_SIZE / 2),
-            hull
-        })
+            current_position: (0, 0),
+            commands: Framer::new(),
+        }
     }
 
-    fn paint(&mut self) -> Result<()> {
-        self.brain.input(self.hull[self.current_position.0][self.current_position.1].val())?;
-        while self.brain.run()? != VMState::Halted {
-            let color = TileColor::from(self.brain.output()?);
-            self.hull[self.current_position.0][self.current_position.1] = color;
-            self.brain.run()?;
-            let dir = self.brain.output()?;
-
-            self.current_direction = if dir == 0 {
-                match self.current_direction {
-                    Direction::Left => Direction::Down,
-                    Direction::Down => Direction::Right,
-                    Direction::Right => Direction::Up,
-                    Direction::Up => Direction::Left,
-                }
-            } else if dir == 1{
-                match self.current_direction {
-                    Direction::Left => Direction::Up,
-                    Direction::Up => Direction::Right,
-                    Direction::Right => Direction::Down,
-                    Direction::Down => Direction::Left,
-                }
-            } else {
-                panic!("Unkown turn signal");
-            };
-            self.move();
-            self.brain.input(self.hull[self.current_position.0][self.current_position.1].val())?;
-        }
-        Ok(())
+    fn turn(&mut self, turn: Turn) {
+        self.current_direction = match turn {
+            Turn::Left => match self.current_direction {
+                Direction::Left => Direction::Down,
+                Direction::Down => Direction::Right,
+                Direction::Right => Direction::Up,
+                Direction::Up => Direction::Left,
+            },
+            Turn::Right => match self.current_direction {
+                Direction::Left => Direction::Up,
+                Direction::Up => Direction::Right,
+                Direction::Right => Direction::Down,
+                Direction::Down => Direction::Left,
+            },
+        };
     }
 
-    fn move(&mut self) {
+    fn advance(&mut self) {
         self.current_position.0 += match self.current_direction {
             Direction::Left  => -1,
             Direction::Right => 1,
@@ -114,35 +97,32 @@ impl Robot {
             Direction::Up    => 1,
             Direction::Down  => -1
         };
-        if self.current_position.0 <= 0 {
-            panic!("Board to small in x");
-        }
-        if self.current_position.0 >= INITIAL_SIZE -  {
-            panic!("Board to small in x");
-        }
-        if self.current_position.1 <= 0 {
-            panic!("Board to small in y");
-        }
-        if self.current_position.1 >= INITIAL_SIZE -  {
-            panic!("Board to small in y");
-        }
     }
 }
 
+impl InputProvider for Robot {
+    /// The camera looks at the panel under the robot, all panels start black.
+    fn provide(&mut self) -> Option<MemoryValueType> {
+        Some(*self.panels.get(&self.current_position).unwrap_or(&TileColor::Black) as MemoryValueType)
+    }
+}
 
-fn solution_1(program: &[MemoryValueType]) -> Result<MemoryValueType> {
-    let mut robot = Robot::new(program)?;
-    robot.paint()?;
-
-    let mut changed = 0;
-    for xs in robot.hull {
-        for y in xs {
-            if y != TileColor::Unchanged {
-                changed += 1;
-            }
+impl Device for Robot {
+    fn output(&mut self, value: MemoryValueType) -> device::Result<()> {
+        if let Some(command) = self.commands.push(value)? {
+            self.panels.insert(self.current_position, command.color);
+            self.turn(command.turn);
+            self.advance();
         }
+        Ok(())
     }
-    Ok(changed)
+}
+
+
+fn solution_1(program: &[MemoryValueType]) -> device::Result<MemoryValueType> {
+    let mut host = Host::new(VirtualMachine::new(program)?, Robot::new());
+    host.run()?;
+    Ok(host.device().panels.len() as MemoryValueType)
     // let mut vm = VirtualMachine::new(program)?;
 
     // vm.input(1)?;
@@ -178,9 +158,14 @@ fn main() {
     let r = Runfiles::create().unwrap();
     let path = r.rlocation("aoc_solutions/util/input_11");
 
-    let input = read_to_string(path).unwrap();
-    info!("Executing intcode program \"{}\"", input);
-    let program = string_to_program(&input);
+    let program = match load_file(path) {
+        Ok(program) => program,
+        Err(err) => {
+            error!("Could not load program: {}", err);
+            return;
+        }
+    };
+    info!("Executing intcode program with {} values", program.len());
 
     match solution_1(&program) {
         Ok(val) => println!("Solution 1: {}", val),
@@ -0,0 +1,157 @@
+use crate::device::{self, Device, Host, InputProvider};
+use crate::error::ConsoleError;
+use crate::memory::MemoryValueType;
+use crate::virtual_machine::VMState;
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::mem;
+
+pub type Result<T> = std::result::Result<T, ConsoleError>;
+
+/// What a text-based program output.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Output {
+    /// A line of ASCII text without its newline
+    Line(String),
+    /// A value outside of ASCII, passed through as a number
+    Value(MemoryValueType),
+}
+
+/// A device for programs that talk in ASCII, one character per value.
+///
+/// Text that is sent is queued for the machine with a newline appended,
+/// output is collected into lines. An output value outside of ASCII ends an
+/// unfinished line before it is passed on as a number.
+#[derive(Clone, Debug, Default)]
+pub struct Console {
+    inputs: VecDeque<MemoryValueType>,
+    line: String,
+    outputs: Vec<Output>,
+}
+
+impl Console {
+    pub fn new() -> Console {
+        Console::default()
+    }
+
+    /// Queues a line of text, nothing is queued if it is not ASCII.
+    pub fn send(&mut self, text: &str) -> Result<()> {
+        if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+            return Err(ConsoleError::NonAscii(c));
+        }
+        self.inputs.extend(text.bytes().chain(Some(b'\n')).map(MemoryValueType::from));
+        Ok(())
+    }
+
+    /// Takes the finished lines and values.
+    pub fn take_outputs(&mut self) -> Vec<Output> {
+        mem::take(&mut self.outputs)
+    }
+
+    /// Takes the text of an unfinished line, like a prompt.
+    pub fn take_pending(&mut self) -> String {
+        mem::take(&mut self.line)
+    }
+}
+
+impl InputProvider for Console {
+    fn provide(&mut self) -> Option<MemoryValueType> {
+        self.inputs.pop_front()
+    }
+}
+
+impl Device for Console {
+    fn output(&mut self, value: MemoryValueType) -> device::Result<()> {
+        match value {
+            10 => {
+                let line = self.take_pending();
+                self.outputs.push(Output::Line(line));
+            }
+            0..=127 => self.line.push(value as u8 as char),
+            _ => {
+                if !self.line.is_empty() {
+                    let line = self.take_pending();
+                    self.outputs.push(Output::Line(line));
+                }
+                self.outputs.push(Output::Value(value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs a text-based program as a terminal session: its output is written
+/// to `output` and every time it waits for input a line is read from `input`.
+/// Returns the state of the machine when it halted or `input` ended.
+pub fn interact<R: BufRead, W: Write>(host: &mut Host<Console>, mut input: R, mut output: W) -> Result<VMState> {
+    loop {
+        let state = host.run()?;
+        for item in host.device_mut().take_outputs() {
+            match item {
+                Output::Line(line) => writeln!(output, "{}", line)?,
+                Output::Value(value) => writeln!(output, "{}", value)?,
+            }
+        }
+        write!(output, "{}", host.device_mut().take_pending())?;
+        output.flush()?;
+        if state == VMState::Halted {
+            return Ok(state);
+        }
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(state);
+        }
+        host.device_mut().send(line.trim_end_matches(&['\n', '\r'][..]))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::virtual_machine::VirtualMachine;
+
+    // prompts for a line, echoes it and outputs 1000
+    const ECHO: &str = "
+                out #62
+                out #32
+        read:   in c
+                out c
+                eq c, #10, done
+                jz done, #read
+                out #1000
+                halt
+        c:      .word 0
+        done:   .word 0
+    ";
+
+    fn echo() -> Host<Console> {
+        let program = assemble("echo", ECHO).unwrap().code;
+        Host::new(VirtualMachine::new(&program).unwrap(), Console::new())
+    }
+
+    #[test]
+    fn test_console() {
+        let mut host = echo();
+        assert_eq!(host.run().unwrap(), VMState::Blocked);
+        assert!(host.device_mut().take_outputs().is_empty());
+        assert_eq!(host.device_mut().take_pending(), "> ");
+
+        assert_eq!(host.device_mut().send("grüß").unwrap_err().to_string(), "Can not send non ASCII character 'ü'");
+        host.device_mut().send("hello").unwrap();
+        assert_eq!(host.run().unwrap(), VMState::Halted);
+        assert_eq!(host.device_mut().take_outputs(), vec![Output::Line("hello".to_string()), Output::Value(1000)]);
+    }
+
+    #[test]
+    fn test_interact() {
+        let mut output = Vec::new();
+        assert_eq!(interact(&mut echo(), &b"hi\r\nignored\n"[..], &mut output).unwrap(), VMState::Halted);
+        assert_eq!(String::from_utf8(output).unwrap(), "> hi\n1000\n");
+
+        let mut output = Vec::new();
+        assert_eq!(interact(&mut echo(), &b""[..], &mut output).unwrap(), VMState::Blocked);
+        assert_eq!(String::from_utf8(output).unwrap(), "> ");
+    }
+}
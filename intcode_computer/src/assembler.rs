@@ -0,0 +1,392 @@
+use crate::error::AssembleError;
+use crate::linker::Object;
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, ParameterMode};
+use crate::sourcemap::{Location, SourceMap};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub type Result<T> = std::result::Result<T, AssembleError>;
+
+/// A value that is only known once all labels are.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Value {
+    Number(MemoryValueType),
+    Label { name: String, addend: MemoryValueType },
+    /// Address relative to the start of the module
+    Address(usize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Operand {
+    Position(Value),
+    Immediate(Value),
+    Relative(MemoryValueType),
+}
+
+impl Operand {
+    fn mode(&self) -> ParameterMode {
+        match self {
+            Operand::Position(_) => ParameterMode::Position,
+            Operand::Immediate(_) => ParameterMode::Immediate,
+            Operand::Relative(_) => ParameterMode::Relative,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Operand::Position(value) | Operand::Immediate(value) => value,
+            Operand::Relative(offset) => Value::Number(offset),
+        }
+    }
+}
+
+/// Mnemonics of the machine instructions.
+pub const MNEMONICS: &[(&str, Opcode)] = &[
+    ("add", Opcode::Add),
+    ("mul", Opcode::Mul),
+    ("in", Opcode::In),
+    ("out", Opcode::Out),
+    ("jnz", Opcode::JNZ),
+    ("jz", Opcode::JZ),
+    ("lt", Opcode::LT),
+    ("eq", Opcode::EQ),
+    ("rbo", Opcode::RBO),
+    ("halt", Opcode::Halt),
+];
+
+/// Pseudo instructions and the instructions they expand to.
+pub const PSEUDO_INSTRUCTIONS: &[(&str, &[Opcode])] = &[
+    ("mov", &[Opcode::Add]),
+    ("jmp", &[Opcode::JZ]),
+    ("push", &[Opcode::Add, Opcode::RBO]),
+    ("pop", &[Opcode::RBO, Opcode::Add]),
+    ("call", &[Opcode::Add, Opcode::RBO, Opcode::JZ]),
+    ("ret", &[Opcode::RBO, Opcode::JZ]),
+];
+
+pub const DIRECTIVES: &[&str] = &[".global", ".extern", ".word", ".zero", ".file", ".loc"];
+
+/// Assembles one module into a relocatable object.
+///
+/// Every line holds an optional `label:`, an instruction or directive and an
+/// optional `; comment`. Instructions are written as lowercase mnemonics
+/// followed by comma separated operands:
+///
+/// * `#value` is an immediate, `[rb+n]` a relative and a bare `value` a
+///   position mode operand. A value is a number, a label or `label+n`.
+/// * Jumps take their target like any other operand, so `jz #0, #loop` jumps
+///   to `loop` while `jz #0, [rb-1]` jumps to the address on the stack.
+///
+/// Pseudo instructions use the relative base as a stack pointer that points
+/// to the next free word, the stack grows upwards:
+///
+/// * `mov src, dst` and `jmp target`
+/// * `push src` and `pop dst`. Relative operands of `pop` are relative to the
+///   stack pointer after popping.
+/// * `call target` pushes the return address and jumps, `ret` pops it and
+///   jumps back.
+///
+/// Directives are `.global name, ...` to export labels, `.extern name, ...`
+/// to reference labels of other modules, `.word value, ...` for data and
+/// `.zero n` for `n` zero words.
+///
+/// The object maps every word to the line it was assembled from. Generated
+/// code can point to its own source instead: `.file name` sets the file and
+/// `.loc n` the line of the following words, `.loc 0` leaves them unmapped.
+pub fn assemble(name: &str, source: &str) -> Result<Object> {
+    let mut words: Vec<(usize, Value)> = Vec::new();
+    let mut labels = BTreeMap::new();
+    let mut globals = Vec::new();
+    let mut externs = BTreeSet::new();
+    let mut source_map = SourceMap::default();
+    let mut file = name.to_string();
+    let mut source_line = None;
+
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let first = words.len();
+        let syntax = |message: String| AssembleError::Syntax { line, message };
+        let (defined, text) = split_labels(text);
+        for label in defined {
+            if labels.insert(label.to_string(), words.len()).is_some() {
+                return Err(AssembleError::DuplicateLabel { line, name: label.to_string() });
+            }
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(space) => (&text[..space], text[space..].trim()),
+            None => (text, ""),
+        };
+        let args: Vec<&str> = if rest.is_empty() { Vec::new() } else { rest.split(',').map(str::trim).collect() };
+
+        match mnemonic {
+            ".global" | ".extern" => {
+                for name in &args {
+                    if !is_identifier(name) {
+                        return Err(syntax(format!("invalid label `{}`", name)));
+                    }
+                    if mnemonic == ".global" {
+                        globals.push((line, name.to_string()));
+                    } else {
+                        externs.insert(name.to_string());
+                    }
+                }
+            }
+            ".word" => for arg in &args {
+                let value = parse_value(arg).ok_or_else(|| syntax(format!("invalid value `{}`", arg)))?;
+                words.push((line, value));
+            },
+            ".zero" => {
+                let count = match args.as_slice() {
+                    [count] => count.parse::<usize>().map_err(|_| syntax(format!("invalid count `{}`", count)))?,
+                    _ => return Err(syntax("`.zero` takes one count".to_string())),
+                };
+                words.extend((0..count).map(|_| (line, Value::Number(0))));
+            }
+            ".file" => match args.as_slice() {
+                [name] if !name.is_empty() && !name.contains(char::is_whitespace) => file = name.to_string(),
+                _ => return Err(syntax("`.file` takes one name without spaces".to_string())),
+            },
+            ".loc" => match args.as_slice() {
+                [n] => source_line = Some(n.parse::<usize>().map_err(|_| syntax(format!("invalid line `{}`", n)))?),
+                _ => return Err(syntax("`.loc` takes one line number".to_string())),
+            },
+            _ => {
+                let mut operands = Vec::with_capacity(args.len());
+                for arg in &args {
+                    operands.push(parse_operand(arg).ok_or_else(|| syntax(format!("invalid operand `{}`", arg)))?);
+                }
+                for (opcode, operands) in expand(mnemonic, operands, words.len()).ok_or_else(|| {
+                    syntax(format!("unknown instruction `{}` with {} operands", mnemonic, args.len()))
+                })? {
+                    if let Some(destination) = opcode.destination() {
+                        if operands[destination].mode() == ParameterMode::Immediate {
+                            return Err(AssembleError::ImmediateDestination { line, mnemonic: mnemonic.to_string() });
+                        }
+                    }
+                    let modes = operands.iter()
+                        .zip(&[100, 1000, 10000])
+                        .map(|(operand, factor)| operand.mode() as MemoryValueType * factor)
+                        .sum::<MemoryValueType>();
+                    words.push((line, Value::Number(opcode as MemoryValueType + modes)));
+                    words.extend(operands.into_iter().map(|operand| (line, operand.into_value())));
+                }
+            }
+        }
+
+        let mapped = match source_line {
+            None => Some(line),
+            Some(0) => None,
+            Some(n) => Some(n),
+        };
+        if let Some(mapped) = mapped {
+            source_map.insert(first..words.len(), Location { file: file.clone(), line: mapped });
+        }
+    }
+
+    let mut object = Object::new(name, Vec::with_capacity(words.len()));
+    object.source = source_map;
+    for (offset, (line, value)) in words.into_iter().enumerate() {
+        let word = match value {
+            Value::Number(number) => number,
+            Value::Address(address) => {
+                object.relocate(offset);
+                address as MemoryValueType
+            }
+            Value::Label { name, addend } => match labels.get(&name) {
+                Some(&address) => {
+                    object.relocate(offset);
+                    address as MemoryValueType + addend
+                }
+                None if externs.contains(&name) => {
+                    object.reference(offset, &name, addend);
+                    0
+                }
+                None => return Err(AssembleError::UndefinedLabel { line, name }),
+            },
+        };
+        object.code.push(word);
+    }
+
+    let exported: BTreeSet<&str> = globals.iter().map(|(_, name)| name.as_str()).collect();
+    for (line, name) in &globals {
+        if !labels.contains_key(name) {
+            return Err(AssembleError::UndefinedLabel { line: *line, name: name.clone() });
+        }
+    }
+    for (label, &offset) in &labels {
+        object.define(label, offset, exported.contains(label.as_str()));
+    }
+    Ok(object)
+}
+
+/// Labels defined in the source and the lines defining them, even if the
+/// source does not assemble.
+pub fn labels(source: &str) -> Vec<(String, usize)> {
+    source.lines()
+        .enumerate()
+        .flat_map(|(i, text)| split_labels(text).0.into_iter().map(move |label| (label.to_string(), i + 1)))
+        .collect()
+}
+
+/// Splits a line into the labels it defines and the remaining instruction,
+/// dropping the comment.
+fn split_labels(text: &str) -> (Vec<&str>, &str) {
+    let mut text = text.split(';').next().unwrap_or("").trim();
+    let mut labels = Vec::new();
+    while let Some(colon) = text.find(':') {
+        let label = text[..colon].trim();
+        if !is_identifier(label) {
+            break;
+        }
+        labels.push(label);
+        text = text[colon + 1..].trim();
+    }
+    (labels, text)
+}
+
+/// Turns a mnemonic into machine instructions. `address` is the offset of the
+/// first generated word.
+fn expand(mnemonic: &str, mut operands: Vec<Operand>, address: usize) -> Option<Vec<(Opcode, Vec<Operand>)>> {
+    let zero = || Operand::Immediate(Value::Number(0));
+    let rbo = |n| (Opcode::RBO, vec![Operand::Immediate(Value::Number(n))]);
+    let instructions = match (mnemonic, operands.len()) {
+        ("mov", 2) => {
+            let destination = operands.pop()?;
+            vec![(Opcode::Add, vec![operands.pop()?, zero(), destination])]
+        }
+        ("jmp", 1) => vec![(Opcode::JZ, vec![zero(), operands.pop()?])],
+        ("push", 1) => vec![(Opcode::Add, vec![operands.pop()?, zero(), Operand::Relative(0)]), rbo(1)],
+        ("pop", 1) => vec![rbo(-1), (Opcode::Add, vec![Operand::Relative(0), zero(), operands.pop()?])],
+        ("call", 1) => {
+            // the return address is the word after the 9 words of this sequence
+            let back = Operand::Immediate(Value::Address(address + 9));
+            vec![(Opcode::Add, vec![back, zero(), Operand::Relative(0)]), rbo(1), (Opcode::JZ, vec![zero(), operands.pop()?])]
+        }
+        ("ret", 0) => vec![rbo(-1), (Opcode::JZ, vec![zero(), Operand::Relative(0)])],
+        _ => {
+            let &(_, opcode) = MNEMONICS.iter().find(|(name, _)| *name == mnemonic)?;
+            if operands.len() != opcode.parameter_count() {
+                return None;
+            }
+            vec![(opcode, operands)]
+        }
+    };
+    Some(instructions)
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    if let Ok(number) = s.parse() {
+        return Some(Value::Number(number));
+    }
+    let (name, addend) = match s.rfind(&['+', '-'][..]) {
+        Some(sign) => (s[..sign].trim(), s[sign..].replace(' ', "").parse().ok()?),
+        None => (s, 0),
+    };
+    if is_identifier(name) {
+        Some(Value::Label { name: name.to_string(), addend })
+    } else {
+        None
+    }
+}
+
+fn parse_operand(s: &str) -> Option<Operand> {
+    if s.starts_with('#') {
+        return parse_value(s[1..].trim()).map(Operand::Immediate);
+    }
+    if s.starts_with('[') && s.ends_with(']') {
+        let inner = s[1..s.len() - 1].replace(' ', "");
+        if !inner.starts_with("rb") {
+            return None;
+        }
+        return match &inner[2..] {
+            "" => Some(Operand::Relative(0)),
+            offset => offset.parse().ok().map(Operand::Relative),
+        };
+    }
+    parse_value(s).map(Operand::Position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linker::Linker;
+    use crate::virtual_machine::{VirtualMachine, VMState};
+
+    #[test]
+    fn test_encoding() {
+        let source = "
+            .global start
+            start:  in value          ; read a number
+                    lt value, #8, [rb+1]
+                    jnz [rb + 1], #small
+                    out #0
+                    halt
+            small:  out value+0
+                    halt
+            value:  .word -1
+        ";
+        let object = assemble("compare", source).unwrap();
+        assert_eq!(object.code, vec![3,15, 21007,15,8,1, 1205,1,12, 104,0, 99, 4,15, 99, -1]);
+        assert_eq!(object.relocations.iter().map(|r| r.offset).collect::<Vec<_>>(), vec![1, 3, 8, 13]);
+        assert!(object.symbols.iter().any(|s| s.name == "start" && s.global));
+        assert!(object.symbols.iter().any(|s| s.name == "small" && !s.global && s.offset == 12));
+    }
+
+    #[test]
+    fn test_stack() {
+        let source = "
+            .extern stack
+                    rbo #stack
+                    push #20
+                    push #22
+                    call #add
+                    rbo #-1           ; drop the second argument
+                    pop [rb+1]        ; the stack pointer has already moved
+                    out [rb+1]
+                    halt
+            ; adds the two arguments, the result replaces the first one
+            add:    add [rb-3], [rb-2], [rb-3]
+                    ret
+        ";
+        let mut stack = Object::new("stack", Vec::new());
+        stack.define("stack", 0, true);
+        let image = Linker::new().add(assemble("main", source).unwrap()).add(stack).link().unwrap();
+
+        let mut vm = VirtualMachine::new(&image.program).unwrap();
+        assert_eq!(vm.run().unwrap(), VMState::Blocked);
+        assert_eq!(vm.output().unwrap(), 42);
+        assert_eq!(vm.run().unwrap(), VMState::Halted);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(assemble("a", "add #1, #2, #3").unwrap_err(),
+                   AssembleError::ImmediateDestination { line: 1, mnemonic: "add".to_string() });
+        assert_eq!(assemble("a", "\njz #0, #nowhere").unwrap_err(),
+                   AssembleError::UndefinedLabel { line: 2, name: "nowhere".to_string() });
+        assert_eq!(assemble("a", "x: halt\nx: halt").unwrap_err(),
+                   AssembleError::DuplicateLabel { line: 2, name: "x".to_string() });
+        match assemble("a", "out 1, 2") {
+            Err(AssembleError::Syntax { line: 1, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match assemble("a", "out [sp+1]") {
+            Err(AssembleError::Syntax { line: 1, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(labels("a: b: halt ; c:\n\nd:"), vec![("a".to_string(), 1), ("b".to_string(), 1), ("d".to_string(), 3)]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Machines that run as futures, so networks of them can be executed as
+//! tasks on an executor instead of being polled by hand.
+//!
+//! ```
+//! use futures::channel::mpsc;
+//! use futures::executor::block_on;
+//! use futures::stream;
+//! use intcode_computer::async_machine::AsyncVirtualMachine;
+//! use intcode_computer::virtual_machine::VirtualMachine;
+//!
+//! // doubles every input
+//! let vm = VirtualMachine::new(&[3,9, 1002,9,2,9, 4,9, 1105,1,0]).unwrap();
+//! let (output, mut outputs) = mpsc::unbounded();
+//! let mut machine = AsyncVirtualMachine::new(vm, stream::iter(vec![1, 2, 3]), output);
+//! // the machine asks for a fourth value once the inputs are used up
+//! assert!(block_on(machine.run()).is_err());
+//! let doubled: Vec<i64> = std::iter::from_fn(|| outputs.try_next().ok().flatten()).collect();
+//! assert_eq!(doubled, vec![2, 4, 6]);
+//! ```
+
+use crate::error::{Result, VMError};
+use crate::memory::MemoryValueType;
+use crate::virtual_machine::{VirtualMachine, VMState};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+
+/// A `VirtualMachine` whose `In` instructions await the next value of an
+/// input stream and whose `Out` instructions send into an output sink.
+///
+/// Between two I/O instructions the machine runs without yielding.
+pub struct AsyncVirtualMachine<I, O> {
+    vm: VirtualMachine,
+    input: I,
+    output: O,
+}
+
+impl<I, O> AsyncVirtualMachine<I, O>
+where
+    I: Stream<Item = MemoryValueType> + Unpin,
+    O: Sink<MemoryValueType> + Unpin,
+{
+    pub fn new(vm: VirtualMachine, input: I, output: O) -> Self {
+        AsyncVirtualMachine { vm, input, output }
+    }
+
+    pub fn vm(&self) -> &VirtualMachine {
+        &self.vm
+    }
+
+    pub fn into_parts(self) -> (VirtualMachine, I, O) {
+        (self.vm, self.input, self.output)
+    }
+
+    /// Runs until the machine halts. Fails with `InputClosed` if the input
+    /// ends while the machine waits for a value and with `OutputClosed` if
+    /// the sink does not accept a value.
+    pub async fn run(&mut self) -> Result<()> {
+        loop {
+            match self.vm.run()? {
+                VMState::Halted => return Ok(()),
+                _ if self.vm.output_register().is_some() => {
+                    let value = self.vm.output()?;
+                    self.output.send(value).await.map_err(|_| VMError::OutputClosed)?;
+                }
+                _ => {
+                    let value = self.input.next().await.ok_or(VMError::InputClosed)?;
+                    self.vm.input(value)?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{ECHO, FEEDBACK};
+    use futures::channel::mpsc;
+    use futures::executor::{block_on, LocalPool};
+    use futures::future::try_join_all;
+    use futures::stream;
+    use futures::task::LocalSpawnExt;
+
+    #[test]
+    fn test_feedback_loop() {
+        let phases = [9, 8, 7, 6, 5];
+        let (senders, receivers): (Vec<_>, Vec<_>) = phases.iter().map(|_| mpsc::unbounded()).unzip();
+        for (sender, &phase) in senders.iter().zip(&phases) {
+            sender.unbounded_send(phase).unwrap();
+        }
+        senders[0].unbounded_send(0).unwrap();
+
+        let mut machines: Vec<_> = receivers.into_iter()
+            .zip(senders.iter().cycle().skip(1))
+            .map(|(input, output)| AsyncVirtualMachine::new(VirtualMachine::new(FEEDBACK).unwrap(), input, output.clone()))
+            .collect();
+        block_on(try_join_all(machines.iter_mut().map(AsyncVirtualMachine::run))).unwrap();
+
+        let (_, mut input, _) = machines.swap_remove(0).into_parts();
+        assert_eq!(input.try_next().unwrap(), Some(139629729));
+    }
+
+    #[test]
+    fn test_tasks() {
+        // echoes two values
+        let program = [3,0, 4,0, 3,0, 4,0, 99];
+        let mut pool = LocalPool::new();
+        let (first, input) = mpsc::unbounded();
+        let (output, mut last) = mpsc::unbounded();
+        let (middle, middle_input) = mpsc::unbounded();
+        for (input, output) in vec![(input, middle), (middle_input, output)] {
+            let mut machine = AsyncVirtualMachine::new(VirtualMachine::new(&program).unwrap(), input, output);
+            pool.spawner().spawn_local(async move { machine.run().await.unwrap() }).unwrap();
+        }
+        first.unbounded_send(4).unwrap();
+        pool.run_until_stalled();
+        assert_eq!(last.try_next().unwrap(), Some(4));
+        first.unbounded_send(2).unwrap();
+        pool.run();
+        assert_eq!(last.try_next().unwrap(), Some(2));
+        assert_eq!(last.try_next().unwrap(), None);
+    }
+
+    #[test]
+    fn test_closed() {
+        let mut machine = AsyncVirtualMachine::new(VirtualMachine::new(ECHO).unwrap(), stream::empty(), mpsc::unbounded().0);
+        assert_eq!(block_on(machine.run()).unwrap_err().to_string(), VMError::InputClosed.to_string());
+
+        let (output, receiver) = mpsc::unbounded();
+        drop(receiver);
+        let mut machine = AsyncVirtualMachine::new(VirtualMachine::new(ECHO).unwrap(), stream::iter(vec![1]), output);
+        assert_eq!(block_on(machine.run()).unwrap_err().to_string(), VMError::OutputClosed.to_string());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::assembler::MNEMONICS;
+use crate::error::Result;
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, ParameterMode, decode_opcode, decode_parameter_modes};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+/// A decoded instruction as found in the program text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Instruction {
+    pub address: usize,
+    pub opcode: Opcode,
+    pub modes: [ParameterMode; 3],
+    pub params: Vec<MemoryValueType>,
+}
+
+impl Instruction {
+    /// Decodes the instruction at the given address. Parameters beyond the end
+    /// of the program read as 0, just like uninitialized memory.
+    pub fn decode(program: &[MemoryValueType], address: usize) -> Result<Instruction> {
+        let word = program.get(address).cloned().unwrap_or(0);
+        let opcode = decode_opcode(word)?;
+        let modes = decode_parameter_modes(word)?;
+        let params = (1..=opcode.parameter_count())
+            .map(|i| program.get(address + i).cloned().unwrap_or(0))
+            .collect();
+        Ok(Instruction { address, opcode, modes, params })
+    }
+
+    /// Number of words including the instruction word itself.
+    pub fn length(&self) -> usize {
+        self.params.len() + 1
+    }
+
+    /// Target of a jump if it is given as an immediate.
+    pub fn static_target(&self) -> Option<usize> {
+        match self.opcode {
+            Opcode::JNZ | Opcode::JZ if self.modes[1] == ParameterMode::Immediate && self.params[1] >= 0 => {
+                Some(self.params[1] as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Writes the instruction in the syntax of the `assembler`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = MNEMONICS.iter().find(|(_, opcode)| *opcode == self.opcode).map_or("?", |(name, _)| name);
+        write!(f, "{}", mnemonic)?;
+        for (i, (param, mode)) in self.params.iter().zip(&self.modes).enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            match mode {
+                ParameterMode::Position => write!(f, "{}{}", separator, param)?,
+                ParameterMode::Immediate => write!(f, "{}#{}", separator, param)?,
+                ParameterMode::Relative if *param < 0 => write!(f, "{}[rb{}]", separator, param)?,
+                ParameterMode::Relative => write!(f, "{}[rb+{}]", separator, param)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How control leaves a basic block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Terminator {
+    /// Execution continues with the block at the given address
+    FallThrough(usize),
+    /// Jump instruction. The target is `None` if it is only known at runtime.
+    Branch { target: Option<usize>, fallthrough: usize },
+    Halt,
+    /// Execution runs into a word that is not a valid instruction
+    Invalid,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+impl BasicBlock {
+    pub fn successors(&self) -> Vec<usize> {
+        match self.terminator {
+            Terminator::FallThrough(next) => vec![next],
+            Terminator::Branch { target, fallthrough } => target.into_iter().chain(Some(fallthrough)).collect(),
+            Terminator::Halt | Terminator::Invalid => vec![],
+        }
+    }
+}
+
+/// Control flow graph of the code reachable from address 0.
+///
+/// Both exits of every jump are kept, even if an immediate condition decides
+/// it: the immediate may be overwritten at runtime. Only jumps with immediate
+/// targets can be followed, so code that is only reached through computed
+/// jumps (e.g. returns via the relative base) is missing. `is_complete` tells
+/// whether that can be the case.
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph {
+    blocks: BTreeMap<usize, BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    pub fn recover(program: &[MemoryValueType]) -> ControlFlowGraph {
+        // first pass: find all reachable instructions and the block leaders
+        let mut instructions = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        let mut visited = HashSet::new();
+        let mut worklist = vec![0];
+
+        while let Some(address) = worklist.pop() {
+            if !visited.insert(address) {
+                continue;
+            }
+            let instruction = match Instruction::decode(program, address) {
+                Ok(instruction) => instruction,
+                Err(_) => continue,
+            };
+            let next = address + instruction.length();
+            match instruction.opcode {
+                Opcode::Halt => (),
+                Opcode::JNZ | Opcode::JZ => {
+                    if let Some(target) = instruction.static_target() {
+                        leaders.insert(target);
+                        worklist.push(target);
+                    }
+                    leaders.insert(next);
+                    worklist.push(next);
+                }
+                _ => worklist.push(next),
+            }
+            instructions.insert(address, instruction);
+        }
+
+        // second pass: cut the instruction stream at every leader
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let mut block = Vec::new();
+            let mut address = start;
+            let terminator = loop {
+                let instruction = match instructions.get(&address) {
+                    Some(instruction) => instruction.clone(),
+                    None => break Terminator::Invalid,
+                };
+                let next = address + instruction.length();
+                let terminator = match instruction.opcode {
+                    Opcode::Halt => Some(Terminator::Halt),
+                    Opcode::JNZ | Opcode::JZ => Some(
+                        Terminator::Branch { target: instruction.static_target(), fallthrough: next }
+                    ),
+                    _ if leaders.contains(&next) => Some(Terminator::FallThrough(next)),
+                    _ => None,
+                };
+                block.push(instruction);
+                match terminator {
+                    Some(terminator) => break terminator,
+                    None => address = next,
+                }
+            };
+            blocks.insert(start, BasicBlock { start, instructions: block, terminator });
+        }
+
+        ControlFlowGraph { blocks }
+    }
+
+    pub fn block(&self, start: usize) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    /// All blocks ordered by address.
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    /// Whether every jump target is known and no path runs into an invalid
+    /// instruction.
+    pub fn is_complete(&self) -> bool {
+        self.blocks.values().all(|block| match block.terminator {
+            Terminator::Branch { target: None, .. } | Terminator::Invalid => false,
+            _ => true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks() {
+        // day 05 example: outputs 0 if the input is zero, 1 otherwise
+        let program = vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1];
+        let cfg = ControlFlowGraph::recover(&program);
+        let starts: Vec<usize> = cfg.blocks().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0, 5, 9]);
+        assert_eq!(cfg.block(0).unwrap().terminator, Terminator::Branch { target: Some(9), fallthrough: 5 });
+        assert_eq!(cfg.block(5).unwrap().terminator, Terminator::FallThrough(9));
+        assert_eq!(cfg.block(9).unwrap().terminator, Terminator::Halt);
+        assert_eq!(cfg.block(9).unwrap().instructions.len(), 2);
+        assert!(cfg.is_complete());
+        assert_eq!(cfg.block(0).unwrap().instructions[1].to_string(), "jnz #-1, #9");
+    }
+
+    #[test]
+    fn test_display() {
+        let program = [21007, 15, 8, 1, 204, -3, 99];
+        let text: Vec<String> = [0, 4, 6].iter().map(|&at| Instruction::decode(&program, at).unwrap().to_string()).collect();
+        assert_eq!(text, vec!["lt 15, #8, [rb+1]", "out [rb-3]", "halt"]);
+    }
+
+    #[test]
+    fn test_dynamic_jumps() {
+        let cfg = ControlFlowGraph::recover(&[1106,0,4,99,99]);
+        assert_eq!(cfg.block(0).unwrap().successors(), vec![4, 3]);
+        assert!(cfg.is_complete());
+
+        let cfg = ControlFlowGraph::recover(&[2106,0,0]);
+        assert_eq!(cfg.block(0).unwrap().terminator, Terminator::Branch { target: None, fallthrough: 3 });
+        assert_eq!(cfg.block(3).unwrap().terminator, Terminator::Invalid);
+        assert!(!cfg.is_complete());
+    }
+}
@@ -0,0 +1,794 @@
+use crate::assembler::assemble;
+use crate::error::CompileError;
+use crate::linker::{Image, Linker};
+use crate::memory::MemoryValueType;
+use crate::stdlib;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+pub type Result<T> = std::result::Result<T, CompileError>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Number(MemoryValueType),
+    Ident(String),
+    Punct(&'static str),
+}
+
+// longer operators first so that `<=` is not read as `<`
+const PUNCTUATION: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||",
+    "+", "-", "*", "/", "%", "<", ">", "!", "=", "(", ")", "{", "}", ",", ";",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let text = text.split("//").next().unwrap_or("");
+        let mut rest = text.trim_start();
+        while !rest.is_empty() {
+            let first = rest.chars().next().unwrap_or(' ');
+            let length = if first.is_ascii_digit() {
+                let length = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                let number = rest[..length].parse().map_err(|_| {
+                    CompileError::Syntax { line, message: format!("number `{}` is too large", &rest[..length]) }
+                })?;
+                tokens.push((line, Token::Number(number)));
+                length
+            } else if first.is_ascii_alphabetic() || first == '_' {
+                let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+                tokens.push((line, Token::Ident(rest[..length].to_string())));
+                length
+            } else {
+                let punct = PUNCTUATION.iter().find(|p| rest.starts_with(*p)).ok_or_else(|| {
+                    CompileError::Syntax { line, message: format!("unexpected character `{}`", first) }
+                })?;
+                tokens.push((line, Token::Punct(punct)));
+                punct.len()
+            };
+            rest = rest[length..].trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum BinaryOp {
+    Add, Sub, Mul, Div, Rem,
+    Less, LessEqual, Greater, GreaterEqual, Equal, NotEqual,
+    And, Or,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(MemoryValueType),
+    Variable(usize, String),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(usize, String, Vec<Expr>),
+}
+
+/// Statements together with the line they start on
+type Block = Vec<(usize, Stmt)>;
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(usize, String, Expr),
+    If(Expr, Block, Block),
+    While(Expr, Block),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug)]
+struct Function {
+    line: usize,
+    name: String,
+    params: Vec<String>,
+    body: Block,
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).or_else(|| self.tokens.last()).map_or(1, |(line, _)| *line)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T> {
+        let found = match self.tokens.get(self.pos) {
+            Some((_, Token::Number(n))) => n.to_string(),
+            Some((_, Token::Ident(name))) => name.clone(),
+            Some((_, Token::Punct(p))) => p.to_string(),
+            None => "end of input".to_string(),
+        };
+        Err(CompileError::Syntax { line: self.line(), message: format!("expected {}, found `{}`", message, found) })
+    }
+
+    fn peek(&self, punct: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Punct(p))) => *p == punct,
+            _ => false,
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Ident(name))) => name == keyword,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.peek(punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            self.error(&format!("`{}`", punct))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Ident(name))) => {
+                self.pos += 1;
+                Ok(name.clone())
+            }
+            _ => self.error("a name"),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function> {
+        let line = self.line();
+        if !self.peek_keyword("fn") {
+            return self.error("`fn`");
+        }
+        self.pos += 1;
+        let name = self.ident()?;
+        self.expect("(")?;
+        let mut params = Vec::new();
+        while !self.eat(")") {
+            if !params.is_empty() {
+                self.expect(",")?;
+            }
+            params.push(self.ident()?);
+        }
+        let body = self.block()?;
+        Ok(Function { line, name, params, body })
+    }
+
+    fn block(&mut self) -> Result<Block> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.eat("}") {
+            let line = self.line();
+            statements.push((line, self.statement()?));
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        let line = self.line();
+        let statement = if self.peek_keyword("let") {
+            self.pos += 1;
+            let name = self.ident()?;
+            self.expect("=")?;
+            Stmt::Let(name, self.expression()?)
+        } else if self.peek_keyword("if") {
+            self.pos += 1;
+            return self.if_statement();
+        } else if self.peek_keyword("while") {
+            self.pos += 1;
+            let condition = self.expression()?;
+            return Ok(Stmt::While(condition, self.block()?));
+        } else if self.peek_keyword("return") {
+            self.pos += 1;
+            Stmt::Return(if self.peek(";") { None } else { Some(self.expression()?) })
+        } else {
+            let expr = self.expression()?;
+            match expr {
+                Expr::Variable(_, name) if self.eat("=") => Stmt::Assign(line, name, self.expression()?),
+                expr => Stmt::Expr(expr),
+            }
+        };
+        self.expect(";")?;
+        Ok(statement)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt> {
+        let condition = self.expression()?;
+        let then = self.block()?;
+        let otherwise = if self.peek_keyword("else") {
+            self.pos += 1;
+            if self.peek_keyword("if") {
+                let line = self.line();
+                self.pos += 1;
+                vec![(line, self.if_statement()?)]
+            } else {
+                self.block()?
+            }
+        } else {
+            Vec::new()
+        };
+        Ok(Stmt::If(condition, then, otherwise))
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        self.binary(0)
+    }
+
+    /// Precedence climbing over the levels of `LEVELS`.
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: &[&[(&str, BinaryOp)]] = &[
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual), ("<=", BinaryOp::LessEqual),
+              (">=", BinaryOp::GreaterEqual), ("<", BinaryOp::Less), (">", BinaryOp::Greater)],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Rem)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(&(_, op)) = LEVELS[level].iter().find(|(punct, _)| self.peek(punct)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.expression()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        let line = self.line();
+        match self.tokens.get(self.pos).cloned() {
+            Some((_, Token::Number(n))) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Some((_, Token::Ident(name))) => {
+                self.pos += 1;
+                if !self.eat("(") {
+                    return Ok(Expr::Variable(line, name));
+                }
+                let mut args = Vec::new();
+                while !self.eat(")") {
+                    if !args.is_empty() {
+                        self.expect(",")?;
+                    }
+                    args.push(self.expression()?);
+                }
+                Ok(Expr::Call(line, name, args))
+            }
+            _ => self.error("an expression"),
+        }
+    }
+}
+
+/// Emits assembler code for one function.
+///
+/// The relative base is the stack pointer as in `stdlib`. A call pushes a
+/// result slot and the arguments, the callee reserves its locals on top of
+/// the return address and evaluates expressions on the stack above them:
+///
+/// ```text
+/// result | arguments | return address | locals | temporaries
+///                                             ^ frame
+/// ```
+struct Codegen<'a> {
+    arities: &'a BTreeMap<String, usize>,
+    out: String,
+    labels: usize,
+    /// Offsets of the variables visible in the current block relative to
+    /// the frame
+    variables: HashMap<String, i64>,
+    /// Locals taken by the variables of the enclosing blocks
+    used: i64,
+    locals: i64,
+    result: i64,
+    /// Words pushed on top of the frame
+    depth: i64,
+}
+
+impl<'a> Codegen<'a> {
+    fn emit(&mut self, instruction: &str) {
+        let _ = writeln!(self.out, "        {}", instruction);
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("l{}", self.labels)
+    }
+
+    fn place(&mut self, label: &str) {
+        let _ = writeln!(self.out, "{}:", label);
+    }
+
+    /// Operand for the word at the given offset from the frame.
+    fn slot(&self, offset: i64) -> String {
+        format!("[rb{:+}]", offset - self.depth)
+    }
+
+    fn variable(&self, line: usize, name: &str) -> Result<String> {
+        match self.variables.get(name) {
+            Some(&offset) => Ok(self.slot(offset)),
+            None => Err(CompileError::UndefinedVariable { line, name: name.to_string() }),
+        }
+    }
+
+    fn rbo(&mut self, words: i64) {
+        if words != 0 {
+            self.emit(&format!("rbo #{}", words));
+            self.depth += words;
+        }
+    }
+
+    fn push(&mut self, operand: &str) {
+        self.emit(&format!("push {}", operand));
+        self.depth += 1;
+    }
+
+    /// Pops the topmost value into the given slot.
+    fn pop_into(&mut self, offset: i64) {
+        self.rbo(-1);
+        let target = self.slot(offset);
+        self.emit(&format!("mov [rb+0], {}", target));
+    }
+
+    fn function(&mut self, function: &Function) -> Result<()> {
+        let params = function.params.len() as i64;
+        self.locals = count_locals(&function.body) as i64;
+        self.result = -self.locals - 2 - params;
+        self.used = 0;
+        self.variables.clear();
+        for (i, name) in function.params.iter().enumerate() {
+            self.variables.insert(name.clone(), i as i64 - self.locals - 1 - params);
+        }
+
+        self.emit(&format!(".loc {}", function.line));
+        self.place(&format!("f_{}", function.name));
+        self.depth = -self.locals;
+        self.rbo(self.locals);
+        self.block(&function.body)?;
+        self.rbo(-self.locals);
+        self.emit("ret");
+        Ok(())
+    }
+
+    fn block(&mut self, block: &[(usize, Stmt)]) -> Result<()> {
+        // the locals of the block are free again after it
+        let (variables, used) = (self.variables.clone(), self.used);
+        for (line, statement) in block {
+            self.emit(&format!(".loc {}", line));
+            self.statement(*line, statement)?;
+        }
+        self.variables = variables;
+        self.used = used;
+        Ok(())
+    }
+
+    fn statement(&mut self, line: usize, statement: &Stmt) -> Result<()> {
+        match statement {
+            Stmt::Let(name, expr) => {
+                self.expression(expr)?;
+                let offset = self.used - self.locals;
+                self.used += 1;
+                self.variables.insert(name.clone(), offset);
+                self.pop_into(offset);
+            }
+            Stmt::Assign(line, name, expr) => {
+                self.variable(*line, name)?;
+                self.expression(expr)?;
+                let offset = self.variables[name];
+                self.pop_into(offset);
+            }
+            Stmt::If(condition, then, otherwise) => {
+                let (skip, end) = (self.label(), self.label());
+                self.condition(condition, &skip)?;
+                self.block(then)?;
+                self.emit(&format!("jmp #{}", end));
+                self.place(&skip);
+                self.block(otherwise)?;
+                self.place(&end);
+            }
+            Stmt::While(condition, body) => {
+                let (start, end) = (self.label(), self.label());
+                self.place(&start);
+                self.condition(condition, &end)?;
+                self.block(body)?;
+                self.emit(&format!(".loc {}", line));
+                self.emit(&format!("jmp #{}", start));
+                self.place(&end);
+            }
+            Stmt::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.expression(expr)?;
+                    let result = self.result;
+                    self.pop_into(result);
+                }
+                let depth = self.depth;
+                self.emit(&format!("rbo #{}", -depth - self.locals));
+                self.emit("ret");
+            }
+            Stmt::Expr(expr) => {
+                self.expression(expr)?;
+                self.rbo(-1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Jumps to the label if the condition is false.
+    fn condition(&mut self, condition: &Expr, otherwise: &str) -> Result<()> {
+        self.expression(condition)?;
+        self.rbo(-1);
+        self.emit(&format!("jz [rb+0], #{}", otherwise));
+        Ok(())
+    }
+
+    /// Pushes the value of the expression.
+    fn expression(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Number(n) => self.push(&format!("#{}", n)),
+            Expr::Variable(line, name) => {
+                let operand = self.variable(*line, name)?;
+                self.push(&operand);
+            }
+            Expr::Negate(expr) => {
+                self.expression(expr)?;
+                self.emit("mul [rb-1], #-1, [rb-1]");
+            }
+            Expr::Not(expr) => {
+                self.expression(expr)?;
+                self.emit("eq [rb-1], #0, [rb-1]");
+            }
+            Expr::Binary(op @ BinaryOp::And, lhs, rhs) | Expr::Binary(op @ BinaryOp::Or, lhs, rhs) => {
+                let end = self.label();
+                self.expression(lhs)?;
+                let jump = if *op == BinaryOp::And { "jz" } else { "jnz" };
+                self.emit(&format!("{} [rb-1], #{}", jump, end));
+                self.rbo(-1);
+                self.expression(rhs)?;
+                self.place(&end);
+                self.emit("eq [rb-1], #0, [rb-1]");
+                self.emit("eq [rb-1], #0, [rb-1]");
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                self.expression(lhs)?;
+                self.expression(rhs)?;
+                self.binary(*op);
+            }
+            Expr::Call(line, name, args) => self.call(*line, name, args)?,
+        }
+        Ok(())
+    }
+
+    /// Combines the two topmost values.
+    fn binary(&mut self, op: BinaryOp) {
+        let (instruction, negate) = match op {
+            BinaryOp::Add => ("add [rb-2], [rb-1], [rb-2]", false),
+            BinaryOp::Mul => ("mul [rb-2], [rb-1], [rb-2]", false),
+            BinaryOp::Sub => {
+                self.emit("mul [rb-1], #-1, [rb-1]");
+                ("add [rb-2], [rb-1], [rb-2]", false)
+            }
+            BinaryOp::Div | BinaryOp::Rem => {
+                self.emit("call #divmod");
+                if op == BinaryOp::Rem {
+                    self.emit("mov [rb-1], [rb-2]");
+                }
+                self.rbo(-1);
+                return;
+            }
+            BinaryOp::Less => ("lt [rb-2], [rb-1], [rb-2]", false),
+            BinaryOp::GreaterEqual => ("lt [rb-2], [rb-1], [rb-2]", true),
+            BinaryOp::Greater => ("lt [rb-1], [rb-2], [rb-2]", false),
+            BinaryOp::LessEqual => ("lt [rb-1], [rb-2], [rb-2]", true),
+            BinaryOp::Equal => ("eq [rb-2], [rb-1], [rb-2]", false),
+            BinaryOp::NotEqual => ("eq [rb-2], [rb-1], [rb-2]", true),
+            BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit"),
+        };
+        self.emit(instruction);
+        self.rbo(-1);
+        if negate {
+            self.emit("eq [rb-1], #0, [rb-1]");
+        }
+    }
+
+    fn call(&mut self, line: usize, name: &str, args: &[Expr]) -> Result<()> {
+        let builtin = match name {
+            "read" => Some(0),
+            "print" => Some(1),
+            _ => None,
+        };
+        let expected = match builtin.or_else(|| self.arities.get(name).cloned()) {
+            Some(expected) => expected,
+            None => return Err(CompileError::UndefinedFunction { line, name: name.to_string() }),
+        };
+        if args.len() != expected {
+            return Err(CompileError::Arity { line, name: name.to_string(), expected, found: args.len() });
+        }
+
+        match name {
+            "read" => {
+                self.emit("in [rb+0]");
+                self.rbo(1);
+            }
+            "print" => {
+                self.expression(&args[0])?;
+                self.emit("out [rb-1]");
+            }
+            _ => {
+                self.push("#0");
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                self.emit(&format!("call #f_{}", name));
+                self.rbo(-(args.len() as i64));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Number of locals a block needs: one for each of its `let`s plus the most
+/// any nested block needs at the same time.
+fn count_locals(statements: &[(usize, Stmt)]) -> usize {
+    let (mut lets, mut most) = (0, 0);
+    for (_, statement) in statements {
+        let nested = match statement {
+            Stmt::Let(..) => {
+                lets += 1;
+                0
+            }
+            Stmt::If(_, then, otherwise) => count_locals(then).max(count_locals(otherwise)),
+            Stmt::While(_, body) => count_locals(body),
+            _ => 0,
+        };
+        most = most.max(lets + nested);
+    }
+    most
+}
+
+/// Translates a program into assembler source for `assembler::assemble`.
+///
+/// A program is a list of functions, execution starts at `fn main()`:
+///
+/// ```text
+/// fn fib(n) {
+///     if n < 2 { return n; }
+///     return fib(n - 1) + fib(n - 2);
+/// }
+///
+/// fn main() {
+///     let n = read();
+///     while n >= 0 {
+///         print(fib(n));
+///         n = n - 1;
+///     }
+/// }
+/// ```
+///
+/// All values are integers, comparisons and `!`, `&&` and `||` yield 0 or 1.
+/// Variables are declared with `let` and can be used from there to the end of
+/// the enclosing block. A `let` of a name that is already visible declares a
+/// new variable that hides the other one until the end of its block.
+/// `read()` returns the next input, `print(x)` outputs `x` and returns it and
+/// functions without `return` return 0. `/` and `%` call `divmod` from
+/// `stdlib`, which has to be linked with the result.
+pub fn to_assembly(source: &str) -> Result<String> {
+    let mut parser = Parser { tokens: tokenize(source)?, pos: 0 };
+    let mut functions = Vec::new();
+    while parser.pos < parser.tokens.len() {
+        functions.push(parser.function()?);
+    }
+
+    let mut arities = BTreeMap::new();
+    for function in &functions {
+        if arities.insert(function.name.clone(), function.params.len()).is_some() {
+            return Err(CompileError::DuplicateFunction { line: function.line, name: function.name.clone() });
+        }
+    }
+    if arities.get("main") != Some(&0) {
+        return Err(CompileError::MissingMain);
+    }
+
+    let mut codegen = Codegen {
+        arities: &arities,
+        out: String::new(),
+        labels: 0,
+        variables: HashMap::new(),
+        used: 0,
+        locals: 0,
+        result: 0,
+        depth: 0,
+    };
+    codegen.out.push_str(".extern stack, divmod\n.loc 0\n        rbo #stack\n        push #0\n        call #f_main\n        halt\n");
+    for function in &functions {
+        codegen.function(function)?;
+    }
+    Ok(codegen.out)
+}
+
+/// Compiles, assembles and links a program with the standard library.
+pub fn compile(source: &str) -> Result<Image> {
+    let mut linker = Linker::new();
+    linker.add(assemble("main", &to_assembly(source)?)?);
+    for object in stdlib::objects()? {
+        linker.add(object);
+    }
+    Ok(linker.link()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_machine::{VirtualMachine, VMState};
+
+    fn run(source: &str, inputs: &[MemoryValueType]) -> Vec<MemoryValueType> {
+        let image = compile(source).unwrap();
+        let mut vm = VirtualMachine::new(&image.program).unwrap();
+        let mut inputs = inputs.iter();
+        let mut outputs = Vec::new();
+        loop {
+            match vm.run().unwrap() {
+                VMState::Halted => return outputs,
+                _ => match vm.output() {
+                    Ok(val) => outputs.push(val),
+                    Err(_) => vm.input(*inputs.next().unwrap()).unwrap(),
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn test_functions() {
+        let source = "
+            // recursion, arguments and early returns
+            fn fib(n) {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn main() {
+                let n = read();
+                while n >= 0 {
+                    print(fib(n));
+                    n = n - 1;
+                }
+            }
+        ";
+        assert_eq!(run(source, &[10]), vec![55, 34, 21, 13, 8, 5, 3, 2, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_expressions() {
+        let source = "
+            fn check(a, b) {
+                print(a - b * 2);
+                print(-a / b);
+                print(a % b);
+                print((a < b) + (a <= b) * 2 + (a > b) * 4 + (a >= b) * 8);
+                print(a == b || a != b && !0);
+                print(a > 100 && nothing());
+            }
+
+            fn nothing() {}
+
+            fn main() {
+                check(read(), read());
+            }
+        ";
+        assert_eq!(run(source, &[17, 5]), vec![7, -3, 2, 12, 1, 0]);
+        assert_eq!(run(source, &[5, 5]), vec![-5, -1, 0, 10, 1, 0]);
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let source = "
+            fn twice(n) {
+                let n = n * 2;
+                return n;
+            }
+
+            fn main() {
+                let x = 1;
+                if 1 { let x = 2; print(x); }
+                print(x);
+                let s = 5;
+                let i = 0;
+                while i < 2 {
+                    let s = i;
+                    i = i + 1;
+                }
+                print(s);
+                let x = twice(x) + 1;
+                print(x);
+            }
+        ";
+        assert_eq!(run(source, &[]), vec![2, 1, 5, 3]);
+    }
+
+    #[test]
+    fn test_primes() {
+        let source = "
+            fn is_prime(n) {
+                let d = 2;
+                while d * d <= n {
+                    if n % d == 0 { return 0; }
+                    d = d + 1;
+                }
+                return n > 1;
+            }
+
+            fn main() {
+                let limit = read();
+                let n = 0;
+                let count = 0;
+                while n < limit {
+                    if is_prime(n) { count = count + 1; } else if n == 4 { print(n); }
+                    n = n + 1;
+                }
+                print(count);
+            }
+        ";
+        assert_eq!(run(source, &[100]), vec![4, 25]);
+    }
+
+    #[test]
+    fn test_errors() {
+        match to_assembly("fn main() { x = 1; }") {
+            Err(CompileError::UndefinedVariable { line: 1, name }) => assert_eq!(name, "x"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            to_assembly("fn main() {\n print(x);\n let x = 1;\n}").unwrap_err().to_string(),
+            "Line 2: undefined variable `x`"
+        );
+        assert_eq!(to_assembly("fn main() { let x = x; }").unwrap_err().to_string(), "Line 1: undefined variable `x`");
+        assert_eq!(
+            to_assembly("fn main() {\n if 1 { let x = 1; }\n print(x);\n}").unwrap_err().to_string(),
+            "Line 3: undefined variable `x`"
+        );
+        match to_assembly("fn main() {\n f(1);\n}\nfn f(a, b) {}") {
+            Err(CompileError::Arity { line: 2, expected: 2, found: 1, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match to_assembly("fn main() { g(); }") {
+            Err(CompileError::UndefinedFunction { .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match to_assembly("fn f() {}") {
+            Err(CompileError::MissingMain) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match to_assembly("fn main() {\n let x = 1 +;\n}") {
+            Err(CompileError::Syntax { line: 2, message }) => assert_eq!(message, "expected an expression, found `;`"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use crate::cfg::ControlFlowGraph;
+use crate::error::{ContainerError, LoadError, TestFailure};
+use crate::loader::{self, Format};
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, ParameterMode, decode_opcode, decode_parameter_modes};
+use crate::util::string_to_program;
+use crate::virtual_machine::{VirtualMachine, VMState};
+use num_enum::TryFromPrimitive;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::Path;
+
+pub type Result<T> = std::result::Result<T, ContainerError>;
+
+/// Tests that do not halt within this many steps fail, unless the container
+/// declares its own limit.
+pub const STEP_LIMIT: usize = 10_000_000;
+
+/// Instruction set levels in the order the puzzles introduced them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Level {
+    /// Day 2: addition, multiplication and halt
+    Arithmetic = 1,
+    /// Day 5, part 1: input, output and immediate parameters
+    InputOutput = 2,
+    /// Day 5, part 2: jumps and comparisons
+    Conditionals = 3,
+    /// Day 9: relative base and relative parameters
+    RelativeBase = 4,
+}
+
+impl Level {
+    /// Level needed to execute an opcode with the given parameter modes.
+    pub fn of(opcode: Opcode, modes: &[ParameterMode]) -> Level {
+        let level = match opcode {
+            Opcode::Add | Opcode::Mul | Opcode::Halt => Level::Arithmetic,
+            Opcode::In | Opcode::Out => Level::InputOutput,
+            Opcode::JNZ | Opcode::JZ | Opcode::LT | Opcode::EQ => Level::Conditionals,
+            Opcode::RBO => Level::RelativeBase,
+        };
+        modes.iter().fold(level, |level, mode| match mode {
+            ParameterMode::Position => level,
+            ParameterMode::Immediate => level.max(Level::InputOutput),
+            ParameterMode::Relative => level.max(Level::RelativeBase),
+        })
+    }
+}
+
+/// Lowest level that covers every statically reachable instruction. Code
+/// only reached through computed jumps is not considered.
+pub fn required_level(program: &[MemoryValueType]) -> Level {
+    ControlFlowGraph::recover(program)
+        .blocks()
+        .flat_map(|block| block.instructions.iter())
+        .map(|instruction| Level::of(instruction.opcode, &instruction.modes[..instruction.params.len()]))
+        .max()
+        .unwrap_or(Level::Arithmetic)
+}
+
+/// One run of a program: the inputs it gets, every value it outputs and the
+/// contents of some memory cells once it halted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Expectation {
+    pub inputs: Vec<MemoryValueType>,
+    pub outputs: Vec<MemoryValueType>,
+    pub memory: Vec<(usize, MemoryValueType)>,
+}
+
+impl Expectation {
+    /// Parses `inputs -> outputs` optionally followed by
+    /// `; address = value, ...`.
+    fn parse(s: &str) -> Option<Expectation> {
+        let (io, memory) = match s.find(';') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, ""),
+        };
+        let arrow = io.find("->")?;
+        let inputs = string_to_program(&io[..arrow]).ok()?;
+        let outputs = string_to_program(&io[arrow + 2..]).ok()?;
+        let memory = memory.split(',')
+            .filter(|cell| !cell.trim().is_empty())
+            .map(|cell| {
+                let equals = cell.find('=')?;
+                Some((cell[..equals].trim().parse().ok()?, cell[equals + 1..].trim().parse().ok()?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Expectation { inputs, outputs, memory })
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |values: &[MemoryValueType]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+        write!(f, "{} -> {}", join(&self.inputs), join(&self.outputs))?;
+        for (i, (address, value)) in self.memory.iter().enumerate() {
+            write!(f, "{}{} = {}", if i == 0 { "; " } else { ", " }, address, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Metadata {
+    pub name: Option<String>,
+    /// Advent of Code day the program belongs to
+    pub day: Option<u8>,
+    pub level: Option<Level>,
+    /// Number of memory words the program may use, starting at address 0
+    pub memory: Option<usize>,
+    /// Maximum number of instructions a test may execute
+    pub steps: Option<usize>,
+    pub tests: Vec<Expectation>,
+}
+
+impl Metadata {
+    fn set(&mut self, line: usize, text: &str) -> Result<()> {
+        let colon = text.find(':').ok_or(ContainerError::MalformedHeader { line })?;
+        let key = text[..colon].trim();
+        let value = text[colon + 1..].trim();
+        let invalid = || ContainerError::InvalidValue { line, key: key.to_string(), value: value.to_string() };
+        match key {
+            "name" => self.name = Some(value.to_string()),
+            "day" => self.day = Some(value.parse().map_err(|_| invalid())?),
+            "level" => {
+                let level = value.parse::<u8>().map_err(|_| invalid())?;
+                self.level = Some(Level::try_from(level).map_err(|_| invalid())?);
+            }
+            "memory" => self.memory = Some(value.parse().map_err(|_| invalid())?),
+            "steps" => self.steps = Some(value.parse().map_err(|_| invalid())?),
+            "test" => self.tests.push(Expectation::parse(value).ok_or_else(invalid)?),
+            _ => return Err(ContainerError::UnknownKey { line, key: key.to_string() }),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = &self.name {
+            writeln!(f, "#! name: {}", name)?;
+        }
+        if let Some(day) = self.day {
+            writeln!(f, "#! day: {}", day)?;
+        }
+        if let Some(level) = self.level {
+            writeln!(f, "#! level: {}", level as u8)?;
+        }
+        if let Some(memory) = self.memory {
+            writeln!(f, "#! memory: {}", memory)?;
+        }
+        if let Some(steps) = self.steps {
+            writeln!(f, "#! steps: {}", steps)?;
+        }
+        for test in &self.tests {
+            writeln!(f, "#! test: {}", test)?;
+        }
+        Ok(())
+    }
+}
+
+/// A program together with its metadata.
+///
+/// The metadata is a header of `#! key: value` lines in front of the program
+/// in any format `loader` understands. Blank and comment lines may come before
+/// the header, a header line after the start of the program is an error. As
+/// `#` starts a comment, a container with a text program is still a valid
+/// plain program. The whole container may be gzip compressed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Container {
+    pub metadata: Metadata,
+    pub program: Vec<MemoryValueType>,
+}
+
+impl Container {
+    pub fn new(program: Vec<MemoryValueType>) -> Container {
+        Container { metadata: Metadata::default(), program }
+    }
+
+    pub fn load(bytes: &[u8]) -> Result<Container> {
+        if loader::is_compressed(bytes) {
+            return Container::load(&loader::decompress(bytes)?);
+        }
+        let mut metadata = Metadata::default();
+        let mut pos = 0;
+        let mut line = 1;
+        // the header may follow blank and comment lines
+        while pos < bytes.len() {
+            let end = bytes[pos..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |i| pos + i);
+            if bytes[pos..end].starts_with(b"#!") {
+                let text = std::str::from_utf8(&bytes[pos + 2..end]).map_err(LoadError::from)?;
+                metadata.set(line, text)?;
+            } else if !is_comment(&bytes[pos..end]) {
+                break;
+            }
+            pos = bytes.len().min(end + 1);
+            line += 1;
+        }
+
+        let program = &bytes[pos..];
+        if loader::detect(program) != Format::Binary {
+            let misplaced = program.split(|&b| b == b'\n').position(|text| text.starts_with(b"#!"));
+            if let Some(i) = misplaced {
+                return Err(ContainerError::MisplacedHeader { line: line + i });
+            }
+        }
+        Ok(Container { metadata, program: loader::load(program)? })
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Container> {
+        Container::load(&std::fs::read(path).map_err(LoadError::from)?)
+    }
+
+    /// Serializes the container with the program in the given format.
+    pub fn write(&self, format: Format, compressed: bool) -> loader::Result<Vec<u8>> {
+        let mut data = self.metadata.to_string().into_bytes();
+        data.extend(loader::write(&self.program, format, false)?);
+        if compressed {
+            loader::compress(&data)
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Runs every embedded test.
+    pub fn run_tests(&self) -> Vec<std::result::Result<(), TestFailure>> {
+        self.metadata.tests.iter().map(|test| self.run_test(test)).collect()
+    }
+
+    /// Runs the program with the inputs of the test and checks its outputs,
+    /// its final memory and that it stays within the declared level and
+    /// memory size.
+    pub fn run_test(&self, test: &Expectation) -> std::result::Result<(), TestFailure> {
+        let mut vm = VirtualMachine::new(&self.program)?;
+        let mut inputs = test.inputs.iter();
+        let mut outputs = Vec::new();
+        let limit = self.metadata.steps.unwrap_or(STEP_LIMIT);
+        let mut steps = 0;
+
+        while vm.state() != VMState::Halted {
+            if steps == limit {
+                return Err(TestFailure::StepLimit(limit));
+            }
+            steps += 1;
+
+            let pc = vm.pc();
+            let opcode = decode_opcode(vm[pc])?;
+            let modes = decode_parameter_modes(vm[pc])?;
+            if let Some(declared) = self.metadata.level {
+                let required = Level::of(opcode, &modes[..opcode.parameter_count()]);
+                if required > declared {
+                    return Err(TestFailure::Level { address: pc, required: required as u8, declared: declared as u8 });
+                }
+            }
+            if opcode == Opcode::In {
+                match inputs.next() {
+                    Some(&val) => vm.input(val)?,
+                    None => return Err(TestFailure::MissingInput(test.inputs.len())),
+                }
+            }
+            if let Some(limit) = self.metadata.memory {
+                let accesses = vm.next_accesses()?;
+                let last_word = pc + opcode.parameter_count();
+                let outside = accesses.reads.iter()
+                    .chain(accesses.write.iter())
+                    .chain(Some(&last_word))
+                    .find(|&&access| access >= limit);
+                if let Some(&access) = outside {
+                    return Err(TestFailure::OutOfBounds { address: pc, access, limit });
+                }
+            }
+
+            vm.resume();
+            vm.step()?;
+            if opcode == Opcode::Out {
+                outputs.push(vm.output()?);
+            }
+        }
+
+        if inputs.len() > 0 {
+            return Err(TestFailure::UnusedInputs(inputs.len()));
+        }
+        if outputs != test.outputs {
+            return Err(TestFailure::Outputs { expected: test.outputs.clone(), actual: outputs });
+        }
+        for &(address, expected) in &test.memory {
+            if vm[address] != expected {
+                return Err(TestFailure::Memory { address, expected, actual: vm[address] });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a line is blank or only a comment.
+fn is_comment(line: &[u8]) -> bool {
+    match line.iter().find(|b| !b.is_ascii_whitespace()) {
+        None | Some(b'#') => true,
+        Some(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPARE: &str = "\
+#! name: Equal to 8
+#! day: 5
+#! level: 3
+#! memory: 11
+#! test: 8 -> 1
+#! test: 7 -> 0
+3,9,8,9,10,9,4,9,99,-1,8
+";
+
+    #[test]
+    fn test_header() {
+        let container = Container::load(COMPARE.as_bytes()).unwrap();
+        assert_eq!(container.metadata.name.as_ref().unwrap(), "Equal to 8");
+        assert_eq!(container.metadata.day, Some(5));
+        assert_eq!(container.metadata.level, Some(Level::Conditionals));
+        assert_eq!(container.metadata.tests[1], Expectation { inputs: vec![7], outputs: vec![0], memory: vec![] });
+        assert_eq!(container.program, string_to_program(COMPARE).unwrap());
+        assert_eq!(required_level(&container.program), Level::Conditionals);
+
+        for &(format, compressed) in &[(Format::Text, false), (Format::Binary, true)] {
+            let data = container.write(format, compressed).unwrap();
+            assert_eq!(Container::load(&data).unwrap(), container);
+        }
+
+        let test = Expectation::parse(" -> ; 0 = 3500, 3 = 70").unwrap();
+        assert_eq!(test.memory, vec![(0, 3500), (3, 70)]);
+        assert_eq!(test.to_string(), " -> ; 0 = 3500, 3 = 70");
+
+        match Container::load(b"#! level: 7\n99") {
+            Err(ContainerError::InvalidValue { line: 1, key, .. }) => assert_eq!(key, "level"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match Container::load(b"#! day: 2\n#! author: me\n99") {
+            Err(ContainerError::UnknownKey { line: 2, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let commented = format!("\n# day 05 example\n{}", COMPARE);
+        assert_eq!(Container::load(commented.as_bytes()).unwrap(), container);
+        assert_eq!(
+            Container::load(b"99\n#! name: late\n").unwrap_err().to_string(),
+            "Line 2: header after the start of the program"
+        );
+    }
+
+    #[test]
+    fn test_runner() {
+        let container = Container::load(COMPARE.as_bytes()).unwrap();
+        assert!(container.run_tests().iter().all(|result| result.is_ok()));
+
+        let mut container = Container::new(vec![1,9,10,3,2,3,11,0,99,30,40,50]);
+        container.metadata.level = Some(Level::Arithmetic);
+        container.metadata.memory = Some(12);
+        assert!(container.run_test(&Expectation::parse("->; 0 = 3500").unwrap()).is_ok());
+        match container.run_test(&Expectation::parse("-> 1").unwrap()) {
+            Err(TestFailure::Outputs { actual, .. }) => assert!(actual.is_empty()),
+            other => panic!("unexpected {:?}", other),
+        }
+        match container.run_test(&Expectation::parse("->; 0 = 1").unwrap()) {
+            Err(TestFailure::Memory { address: 0, actual: 3500, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        container.metadata.memory = Some(11);
+        match container.run_test(&Expectation::default()) {
+            Err(TestFailure::OutOfBounds { address: 4, access: 11, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // the quine from day 9 needs relative parameters
+        let mut container = Container::new(vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99]);
+        let quine = Expectation { outputs: container.program.clone(), ..Expectation::default() };
+        assert!(container.run_test(&quine).is_ok());
+        container.metadata.level = Some(Level::Conditionals);
+        match container.run_test(&quine) {
+            Err(TestFailure::Level { address: 0, required: 4, declared: 3 }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let container = Container::load(b"3,0,99").unwrap();
+        match container.run_test(&Expectation::default()) {
+            Err(TestFailure::MissingInput(0)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match container.run_test(&Expectation::parse("1,2 ->").unwrap()) {
+            Err(TestFailure::UnusedInputs(1)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match Container::load(b"#! steps: 100\n1105,1,0").unwrap().run_test(&Expectation::default()) {
+            Err(TestFailure::StepLimit(100)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
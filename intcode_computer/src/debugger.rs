@@ -0,0 +1,275 @@
+//! Execution control for interactive front ends like the DAP server.
+
+use crate::error::Result;
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, decode_opcode};
+use crate::sourcemap::{DebugInfo, Location};
+use crate::virtual_machine::{VirtualMachine, VMState};
+use std::collections::{BTreeSet, VecDeque};
+use std::ops::Range;
+
+/// How far the debugger runs after `Debugger::start`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// Until a breakpoint is hit
+    Continue,
+    /// A single instruction
+    Instruction,
+    /// Until another source line starts, entering calls
+    StepIn,
+    /// Until another source line of the current or a calling function starts
+    StepOver,
+}
+
+/// Why the debugger stopped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stop {
+    Step,
+    Breakpoint,
+    /// Stopped on request, see `Debugger::pause`
+    Paused,
+    /// The next instruction needs input and none is queued
+    Input,
+    Halted,
+}
+
+#[derive(Clone)]
+struct Target {
+    mode: Mode,
+    location: Option<Location>,
+    relative_base: usize,
+}
+
+/// A virtual machine with breakpoints, queued inputs and collected outputs.
+///
+/// Execution is split into `start`, which sets how far to run, and `run`,
+/// which executes a bounded number of instructions, so front ends can react to
+/// requests while a long running program is executing.
+pub struct Debugger {
+    vm: VirtualMachine,
+    info: DebugInfo,
+    breakpoints: BTreeSet<usize>,
+    inputs: VecDeque<MemoryValueType>,
+    outputs: Vec<MemoryValueType>,
+    target: Option<Target>,
+}
+
+impl Debugger {
+    pub fn new(program: &[MemoryValueType], info: DebugInfo) -> Result<Debugger> {
+        Ok(Debugger {
+            vm: VirtualMachine::new(program)?,
+            info,
+            breakpoints: BTreeSet::new(),
+            inputs: VecDeque::new(),
+            outputs: Vec::new(),
+            target: None,
+        })
+    }
+
+    pub fn vm(&self) -> &VirtualMachine {
+        &self.vm
+    }
+
+    pub fn debug_info(&self) -> &DebugInfo {
+        &self.info
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        self.info.location(self.vm.pc())
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    pub fn set_breakpoints<I: IntoIterator<Item = usize>>(&mut self, addresses: I) {
+        self.breakpoints = addresses.into_iter().collect();
+    }
+
+    /// Queues a value for the next `In` instruction.
+    pub fn provide_input(&mut self, value: MemoryValueType) {
+        self.inputs.push_back(value);
+    }
+
+    pub fn pending_inputs(&self) -> impl Iterator<Item = &MemoryValueType> {
+        self.inputs.iter()
+    }
+
+    /// Outputs produced since the last call.
+    pub fn take_outputs(&mut self) -> Vec<MemoryValueType> {
+        std::mem::take(&mut self.outputs)
+    }
+
+    pub fn memory(&self, range: Range<usize>) -> Vec<MemoryValueType> {
+        range.map(|address| self.vm[address]).collect()
+    }
+
+    pub fn write_memory(&mut self, address: usize, value: MemoryValueType) {
+        self.vm[address] = value;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Resumes execution, see `run`. Without any source lines, line steps
+    /// fall back to single instructions.
+    pub fn start(&mut self, mut mode: Mode) {
+        if mode != Mode::Continue && self.info.source.ranges().next().is_none() {
+            mode = Mode::Instruction;
+        }
+        self.target = Some(Target {
+            mode,
+            location: self.location().cloned(),
+            relative_base: self.vm.relative_base(),
+        });
+    }
+
+    pub fn pause(&mut self) -> Stop {
+        self.target = None;
+        Stop::Paused
+    }
+
+    /// Executes at most `limit` instructions. Returns `None` if the machine
+    /// is still running afterwards or has not been started.
+    pub fn run(&mut self, limit: usize) -> Result<Option<Stop>> {
+        let target = match &self.target {
+            Some(target) => target.clone(),
+            None => return Ok(None),
+        };
+        for _ in 0..limit {
+            let stop = match self.execute() {
+                Ok(stop) => stop.or_else(|| self.reached(&target)),
+                Err(err) => {
+                    self.target = None;
+                    return Err(err);
+                }
+            };
+            if stop.is_some() {
+                self.target = None;
+                return Ok(stop);
+            }
+        }
+        Ok(None)
+    }
+
+    fn execute(&mut self) -> Result<Option<Stop>> {
+        if self.vm.state() == VMState::Halted {
+            return Ok(Some(Stop::Halted));
+        }
+        if decode_opcode(self.vm[self.vm.pc()])? == Opcode::In && self.vm.input_register().is_none() {
+            match self.inputs.pop_front() {
+                Some(value) => self.vm.input(value)?,
+                None => return Ok(Some(Stop::Input)),
+            }
+        }
+        self.vm.resume();
+        self.vm.step()?;
+        if self.vm.output_register().is_some() {
+            self.outputs.push(self.vm.output()?);
+        }
+        if self.vm.state() == VMState::Halted {
+            return Ok(Some(Stop::Halted));
+        }
+        Ok(None)
+    }
+
+    fn reached(&self, target: &Target) -> Option<Stop> {
+        let pc = self.vm.pc();
+        if target.mode != Mode::Instruction && self.breakpoints.contains(&pc) {
+            return Some(Stop::Breakpoint);
+        }
+        let new_line = match self.info.location(pc) {
+            Some(location) => target.location.as_ref() != Some(location),
+            None => false,
+        };
+        let stop = match target.mode {
+            Mode::Continue => false,
+            Mode::Instruction => true,
+            Mode::StepIn => new_line,
+            Mode::StepOver => new_line && self.vm.relative_base() <= target.relative_base,
+        };
+        if stop {
+            Some(Stop::Step)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+
+    const SOURCE: &str = "\
+fn square(x) {
+    return x * x;
+}
+
+fn main() {
+    let n = read();
+    while n > 0 {
+        print(square(n));
+        n = n - 1;
+    }
+}
+";
+
+    fn finish(debugger: &mut Debugger, mode: Mode) -> Stop {
+        debugger.start(mode);
+        debugger.run(100_000).unwrap().unwrap()
+    }
+
+    fn line(debugger: &Debugger) -> usize {
+        debugger.location().unwrap().line
+    }
+
+    #[test]
+    fn test_stepping() {
+        let image = compile(SOURCE).unwrap();
+        let mut debugger = Debugger::new(&image.program, image.debug_info()).unwrap();
+
+        let addresses = debugger.debug_info().source.addresses("main", 9);
+        debugger.set_breakpoints(addresses.iter().map(|range| range.start));
+
+        assert_eq!(finish(&mut debugger, Mode::StepIn), Stop::Step);
+        assert_eq!(line(&debugger), 5);
+        assert_eq!(finish(&mut debugger, Mode::Continue), Stop::Input);
+        assert_eq!(line(&debugger), 6);
+        debugger.provide_input(2);
+
+        assert_eq!(finish(&mut debugger, Mode::Continue), Stop::Breakpoint);
+        assert_eq!(line(&debugger), 9);
+        assert_eq!(debugger.take_outputs(), vec![4]);
+
+        // over the loop condition and into the call
+        assert_eq!(finish(&mut debugger, Mode::StepOver), Stop::Step);
+        assert_eq!(line(&debugger), 7);
+        assert_eq!(finish(&mut debugger, Mode::StepOver), Stop::Step);
+        assert_eq!(line(&debugger), 8);
+        assert_eq!(finish(&mut debugger, Mode::StepIn), Stop::Step);
+        assert_eq!(line(&debugger), 2);
+
+        let pc = debugger.vm().pc();
+        assert_eq!(finish(&mut debugger, Mode::Instruction), Stop::Step);
+        assert_ne!(debugger.vm().pc(), pc);
+
+        debugger.set_breakpoints(vec![]);
+        assert_eq!(finish(&mut debugger, Mode::Continue), Stop::Halted);
+        assert_eq!(debugger.take_outputs(), vec![1]);
+        assert!(!debugger.is_running());
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut debugger = Debugger::new(&[1105, 1, 0], DebugInfo::default()).unwrap();
+        debugger.start(Mode::StepOver);
+        assert_eq!(debugger.run(1).unwrap(), Some(Stop::Step));
+        debugger.start(Mode::Continue);
+        assert_eq!(debugger.run(1000).unwrap(), None);
+        assert!(debugger.is_running());
+        assert_eq!(debugger.pause(), Stop::Paused);
+        assert_eq!(debugger.run(1000).unwrap(), None);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::error::DeviceError;
+use crate::memory::MemoryValueType;
+use crate::virtual_machine::{VirtualMachine, VMState};
+use std::collections::VecDeque;
+
+pub type Result<T> = std::result::Result<T, DeviceError>;
+
+/// Answers a machine whenever it executes `In`. Closures returning the next
+/// input are providers as well.
+pub trait InputProvider {
+    /// The next input, `None` if there is nothing to say yet.
+    fn provide(&mut self) -> Option<MemoryValueType>;
+}
+
+impl<F: FnMut() -> Option<MemoryValueType>> InputProvider for F {
+    fn provide(&mut self) -> Option<MemoryValueType> {
+        self()
+    }
+}
+
+impl InputProvider for Box<dyn InputProvider> {
+    fn provide(&mut self) -> Option<MemoryValueType> {
+        (**self).provide()
+    }
+}
+
+/// Hardware attached to a machine: it provides the inputs and takes
+/// everything the machine outputs.
+pub trait Device: InputProvider {
+    fn output(&mut self, value: MemoryValueType) -> Result<()>;
+}
+
+/// A device that answers with queued inputs and records all outputs.
+#[derive(Clone, Debug, Default)]
+pub struct Tape {
+    pub inputs: VecDeque<MemoryValueType>,
+    pub outputs: Vec<MemoryValueType>,
+}
+
+impl Tape {
+    pub fn new(inputs: &[MemoryValueType]) -> Tape {
+        Tape { inputs: inputs.iter().cloned().collect(), outputs: Vec::new() }
+    }
+}
+
+impl InputProvider for Tape {
+    fn provide(&mut self) -> Option<MemoryValueType> {
+        self.inputs.pop_front()
+    }
+}
+
+impl Device for Tape {
+    fn output(&mut self, value: MemoryValueType) -> Result<()> {
+        self.outputs.push(value);
+        Ok(())
+    }
+}
+
+/// A device made of an input provider, the outputs are collected for the
+/// caller.
+#[derive(Clone, Debug, Default)]
+pub struct Collector<P> {
+    pub provider: P,
+    pub outputs: Vec<MemoryValueType>,
+}
+
+impl<P: InputProvider> Collector<P> {
+    pub fn new(provider: P) -> Collector<P> {
+        Collector { provider, outputs: Vec::new() }
+    }
+}
+
+impl<P: InputProvider> InputProvider for Collector<P> {
+    fn provide(&mut self) -> Option<MemoryValueType> {
+        self.provider.provide()
+    }
+}
+
+impl<P: InputProvider> Device for Collector<P> {
+    fn output(&mut self, value: MemoryValueType) -> Result<()> {
+        self.outputs.push(value);
+        Ok(())
+    }
+}
+
+/// A machine with a device plugged in.
+///
+/// ```
+/// use intcode_computer::device::{Host, Tape};
+/// use intcode_computer::virtual_machine::{VirtualMachine, VMState};
+///
+/// // doubles every input
+/// let vm = VirtualMachine::new(&[3,11, 1002,11,2,11, 4,11, 1105,1,0, 0]).unwrap();
+/// let mut host = Host::new(vm, Tape::new(&[1, 2, 3]));
+/// assert_eq!(host.run().unwrap(), VMState::Blocked);
+/// assert_eq!(host.device().outputs, vec![2, 4, 6]);
+/// ```
+pub struct Host<D> {
+    vm: VirtualMachine,
+    device: D,
+}
+
+impl<D: Device> Host<D> {
+    pub fn new(vm: VirtualMachine, device: D) -> Host<D> {
+        Host { vm, device }
+    }
+
+    pub fn vm(&self) -> &VirtualMachine {
+        &self.vm
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn into_parts(self) -> (VirtualMachine, D) {
+        (self.vm, self.device)
+    }
+
+    /// Runs until the machine halts or the device has no input for it, in
+    /// which case the state is `Blocked` and running again asks the device
+    /// again.
+    pub fn run(&mut self) -> Result<VMState> {
+        loop {
+            match self.vm.run()? {
+                VMState::Halted => return Ok(VMState::Halted),
+                _ if self.vm.output_register().is_some() => {
+                    let value = self.vm.output()?;
+                    self.device.output(value)?;
+                }
+                state => match self.device.provide() {
+                    Some(value) => self.vm.input(value)?,
+                    None => return Ok(state),
+                },
+            }
+        }
+    }
+}
+
+impl<P: InputProvider> Host<Collector<P>> {
+    /// A machine that asks `provider` whenever it executes `In`.
+    pub fn with_provider(vm: VirtualMachine, provider: P) -> Host<Collector<P>> {
+        Host::new(vm, Collector::new(provider))
+    }
+
+    /// Takes the values output so far.
+    pub fn take_outputs(&mut self) -> Vec<MemoryValueType> {
+        std::mem::take(&mut self.device.outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts only zeros and ones and answers with the number of ones so far.
+    struct Counter {
+        ones: MemoryValueType,
+    }
+
+    impl InputProvider for Counter {
+        fn provide(&mut self) -> Option<MemoryValueType> {
+            Some(self.ones)
+        }
+    }
+
+    impl Device for Counter {
+        fn output(&mut self, value: MemoryValueType) -> Result<()> {
+            match value {
+                0 => (),
+                1 => self.ones += 1,
+                _ => return Err(DeviceError::InvalidOutput { device: "counter".to_string(), value }),
+            }
+            Ok(())
+        }
+    }
+
+    // outputs 1, 0, 1, then reads once and outputs what it read
+    const PROGRAM: &[MemoryValueType] = &[104,1, 104,0, 104,1, 3,11, 4,11, 99, 0];
+
+    #[test]
+    fn test_host() {
+        let mut host = Host::new(VirtualMachine::new(PROGRAM).unwrap(), Tape::default());
+        assert_eq!(host.run().unwrap(), VMState::Blocked);
+        assert_eq!(host.device().outputs, vec![1, 0, 1]);
+        host.device_mut().inputs.push_back(7);
+        assert_eq!(host.run().unwrap(), VMState::Halted);
+        assert_eq!(host.into_parts().1.outputs, vec![1, 0, 1, 7]);
+
+        let mut host = Host::new(VirtualMachine::new(PROGRAM).unwrap(), Counter { ones: 0 });
+        assert_eq!(host.run().unwrap_err().to_string(), "counter can not handle output 2");
+    }
+
+    #[test]
+    fn test_provider() {
+        // has nothing to say the first two times it is asked
+        let mut asked: MemoryValueType = 0;
+        let provider = move || {
+            asked += 1;
+            if asked < 3 { None } else { Some(asked) }
+        };
+        let provider: Box<dyn InputProvider> = Box::new(provider);
+        let mut host = Host::with_provider(VirtualMachine::new(PROGRAM).unwrap(), provider);
+        assert_eq!(host.run().unwrap(), VMState::Blocked);
+        assert_eq!(host.take_outputs(), vec![1, 0, 1]);
+        assert_eq!(host.run().unwrap(), VMState::Blocked);
+        assert_eq!(host.run().unwrap(), VMState::Halted);
+        assert_eq!(host.take_outputs(), vec![3]);
+    }
+}
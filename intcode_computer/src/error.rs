@@ -1,5 +1,6 @@
 use thiserror::Error;
 use crate::opcode::{Opcode, ParameterMode};
+use crate::memory::MemoryValueType;
 
 use std::convert::TryFrom;
 
@@ -29,6 +30,10 @@ pub enum VMError {
     NegativeAddress,
     #[error("Destination Operand is immediate")]
     ImmediateDestination,
+    #[error("Input ended while the machine is waiting for input")]
+    InputClosed,
+    #[error("Output can not take any more values")]
+    OutputClosed,
     
     // TODO: Check for out of bounds access?
     // #[error("Tried to access location outside defined memory")]
@@ -51,4 +56,317 @@ pub enum MemoryError {
         address: usize,
         page_size: usize
     }
+}
+
+#[derive(Error, Debug)]
+pub enum SymbolicError {
+    #[error("Machine error")]
+    MachineError(#[from] VMError),
+    #[error("Instruction word at {pc} is symbolic")]
+    SymbolicInstruction { pc: usize },
+    #[error("Instruction at {pc} writes to a symbolic address")]
+    SymbolicWrite { pc: usize },
+    #[error("Instruction at {pc} jumps to a symbolic target")]
+    SymbolicJump { pc: usize },
+    #[error("Instruction at {pc} makes the relative base symbolic")]
+    SymbolicRelativeBase { pc: usize },
+    #[error("Exploration exceeded {0} paths")]
+    PathLimit(usize),
+    #[error("Path exceeded {0} steps")]
+    StepLimit(usize),
+    #[error("Constraint `{0}` is not linear")]
+    NonLinear(String),
+    #[error("Symbol s{0} has no domain")]
+    UnboundedSymbol(usize),
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
+    #[error("Invalid value `{token}` at index {index} (byte {offset})")]
+    InvalidValue {
+        token: String,
+        index: usize,
+        offset: usize
+    },
+    #[error("Value `{token}` at index {index} (byte {offset}) does not fit into memory")]
+    OutOfRange {
+        token: String,
+        index: usize,
+        offset: usize
+    },
+    #[error("Missing value at index {index} (byte {offset})")]
+    MissingValue {
+        index: usize,
+        offset: usize
+    },
+    #[error("Expected `,` before `{token}` at index {index} (byte {offset})")]
+    MissingSeparator {
+        token: String,
+        index: usize,
+        offset: usize
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("Could not read program: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Program is not valid UTF-8: {0}")]
+    Utf8(#[from] std::str::Utf8Error),
+    #[error("{0}")]
+    Parse(#[from] ParseError),
+    #[error("Expected a JSON array")]
+    NotAnArray,
+    #[error("Binary program does not start with `ICB1`")]
+    MissingHeader,
+    #[error("Binary program ends in the middle of a value (byte {0})")]
+    Truncated(usize),
+    #[error("Varint at byte {0} does not fit into memory")]
+    Overlong(usize),
+    #[error("Unexpected data after the last value (byte {0})")]
+    TrailingData(usize),
+    #[error("Unexpected `,` at byte {0} of a program with one value per line")]
+    Comma(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum ContainerError {
+    #[error("{0}")]
+    Load(#[from] LoadError),
+    #[error("Header line {line}: expected `#! key: value`")]
+    MalformedHeader {
+        line: usize
+    },
+    #[error("Header line {line}: unknown key `{key}`")]
+    UnknownKey {
+        line: usize,
+        key: String
+    },
+    #[error("Header line {line}: invalid value `{value}` for `{key}`")]
+    InvalidValue {
+        line: usize,
+        key: String,
+        value: String
+    },
+    #[error("Line {line}: header after the start of the program")]
+    MisplacedHeader {
+        line: usize
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum SliceError {
+    #[error("Machine error")]
+    MachineError(#[from] VMError),
+    #[error("Program did not stop within {0} steps")]
+    StepLimit(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum TestFailure {
+    #[error("{0}")]
+    MachineError(#[from] VMError),
+    #[error("Expected outputs {expected:?}, got {actual:?}")]
+    Outputs {
+        expected: Vec<MemoryValueType>,
+        actual: Vec<MemoryValueType>
+    },
+    #[error("Expected {expected} at address {address}, found {actual}")]
+    Memory {
+        address: usize,
+        expected: MemoryValueType,
+        actual: MemoryValueType
+    },
+    #[error("Program asked for more than the {0} given inputs")]
+    MissingInput(usize),
+    #[error("Program halted with {0} inputs left")]
+    UnusedInputs(usize),
+    #[error("Instruction at {address} needs level {required}, but level {declared} is declared")]
+    Level {
+        address: usize,
+        required: u8,
+        declared: u8
+    },
+    #[error("Instruction at {address} accesses {access}, outside of the declared {limit} words")]
+    OutOfBounds {
+        address: usize,
+        access: usize,
+        limit: usize
+    },
+    #[error("Program did not halt within {0} steps")]
+    StepLimit(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum LinkError {
+    #[error("Line {line}: {message}")]
+    Syntax {
+        line: usize,
+        message: String
+    },
+    #[error("{0}")]
+    Parse(#[from] ParseError),
+    #[error("Symbol `{name}` is defined in `{first}` and `{second}`")]
+    DuplicateSymbol {
+        name: String,
+        first: String,
+        second: String
+    },
+    #[error("Undefined symbol `{name}` referenced in `{module}`")]
+    UndefinedSymbol {
+        name: String,
+        module: String
+    },
+    #[error("Offset {offset} is outside of module `{module}`")]
+    OutOfRange {
+        module: String,
+        offset: usize
+    },
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum AssembleError {
+    #[error("Line {line}: {message}")]
+    Syntax {
+        line: usize,
+        message: String
+    },
+    #[error("Line {line}: label `{name}` is already defined")]
+    DuplicateLabel {
+        line: usize,
+        name: String
+    },
+    #[error("Line {line}: undefined label `{name}`")]
+    UndefinedLabel {
+        line: usize,
+        name: String
+    },
+    #[error("Line {line}: `{mnemonic}` can not write to an immediate")]
+    ImmediateDestination {
+        line: usize,
+        mnemonic: String
+    },
+}
+
+impl AssembleError {
+    pub fn line(&self) -> usize {
+        match self {
+            AssembleError::Syntax { line, .. }
+            | AssembleError::DuplicateLabel { line, .. }
+            | AssembleError::UndefinedLabel { line, .. }
+            | AssembleError::ImmediateDestination { line, .. } => *line,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CompileError {
+    #[error("Line {line}: {message}")]
+    Syntax {
+        line: usize,
+        message: String
+    },
+    #[error("Line {line}: undefined variable `{name}`")]
+    UndefinedVariable {
+        line: usize,
+        name: String
+    },
+    #[error("Line {line}: undefined function `{name}`")]
+    UndefinedFunction {
+        line: usize,
+        name: String
+    },
+    #[error("Line {line}: `{name}` takes {expected} arguments but got {found}")]
+    Arity {
+        line: usize,
+        name: String,
+        expected: usize,
+        found: usize
+    },
+    #[error("Line {line}: function `{name}` is already defined")]
+    DuplicateFunction {
+        line: usize,
+        name: String
+    },
+    #[error("Missing `fn main()`")]
+    MissingMain,
+    #[error("{0}")]
+    Assemble(#[from] AssembleError),
+    #[error("{0}")]
+    Link(#[from] LinkError),
+}
+
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("Machine {machine}: {error}")]
+    Machine {
+        machine: usize,
+        error: VMError
+    },
+    #[error("Machine {0} panicked")]
+    Panicked(usize),
+    #[error("Unknown machine {0}")]
+    UnknownMachine(usize),
+    #[error("Deadlock, machines {0:?} wait for each other")]
+    Deadlock(Vec<usize>),
+}
+
+#[derive(Error, Debug)]
+pub enum TopologyError {
+    #[error("Machine {machine}: {error}")]
+    Machine {
+        machine: usize,
+        error: VMError
+    },
+    #[error("Unknown machine {0}")]
+    UnknownMachine(usize),
+    #[error("Deadlock, machines {0:?} wait for input that never comes")]
+    Deadlock(Vec<usize>),
+}
+
+#[derive(Error, Debug)]
+pub enum NetworkError {
+    #[error("Machine {machine}: {error}")]
+    Machine {
+        machine: usize,
+        error: VMError
+    },
+    #[error("Packet to unknown address {0}")]
+    UnknownAddress(MemoryValueType),
+}
+
+#[derive(Error, Debug)]
+pub enum DeviceError {
+    #[error("{0}")]
+    Machine(#[from] VMError),
+    #[error("{0}")]
+    Decode(#[from] DecodeError),
+    #[error("{device} can not handle output {value}")]
+    InvalidOutput {
+        device: String,
+        value: MemoryValueType
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum DecodeError {
+    #[error("{0}")]
+    Machine(#[from] VMError),
+    #[error("Invalid {field} {value}")]
+    InvalidValue {
+        field: &'static str,
+        value: MemoryValueType
+    },
+    #[error("Output ended after {0} values of a message")]
+    Truncated(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum ConsoleError {
+    #[error("Can not send non ASCII character {0:?}")]
+    NonAscii(char),
+    #[error("{0}")]
+    Device(#[from] DeviceError),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
 }
\ No newline at end of file
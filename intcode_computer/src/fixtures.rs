@@ -0,0 +1,15 @@
+//! Programs the tests of several modules run.
+
+use crate::memory::MemoryValueType;
+
+/// Day 07 example of an amplifier in a feedback loop. Five of them with the
+/// phases 9, 8, 7, 6 and 5 and a first input of 0 end with 139629729.
+pub const FEEDBACK: &[MemoryValueType] = &[
+    3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5
+];
+
+/// Outputs the one value it reads and halts.
+pub const ECHO: &[MemoryValueType] = &[3,5, 4,5, 99, 0];
+
+/// Outputs every value it reads.
+pub const ECHO_FOREVER: &[MemoryValueType] = &[3,7, 4,7, 1105,1,0, 0];
@@ -0,0 +1,198 @@
+use crate::error::{self, DecodeError};
+use crate::memory::MemoryValueType;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+
+pub type Result<T> = std::result::Result<T, DecodeError>;
+
+/// A message a program outputs as a fixed number of values.
+pub trait Decode: Sized {
+    /// Number of values in one message
+    const SIZE: usize;
+
+    /// Decodes exactly `SIZE` values.
+    fn decode(values: &[MemoryValueType]) -> Result<Self>;
+}
+
+/// Converts a value with `TryFrom`, e.g. into an enum deriving
+/// `TryFromPrimitive`, and names the field if that fails.
+pub fn field<T: TryFrom<MemoryValueType>>(name: &'static str, value: MemoryValueType) -> Result<T> {
+    T::try_from(value).map_err(|_| DecodeError::InvalidValue { field: name, value })
+}
+
+impl Decode for (MemoryValueType, MemoryValueType) {
+    const SIZE: usize = 2;
+
+    fn decode(values: &[MemoryValueType]) -> Result<Self> {
+        Ok((values[0], values[1]))
+    }
+}
+
+impl Decode for (MemoryValueType, MemoryValueType, MemoryValueType) {
+    const SIZE: usize = 3;
+
+    fn decode(values: &[MemoryValueType]) -> Result<Self> {
+        Ok((values[0], values[1], values[2]))
+    }
+}
+
+/// Collects outputs one at a time until they make up a message.
+#[derive(Clone, Debug)]
+pub struct Framer<M> {
+    buffer: Vec<MemoryValueType>,
+    message: PhantomData<M>,
+}
+
+impl<M: Decode> Default for Framer<M> {
+    fn default() -> Framer<M> {
+        Framer::new()
+    }
+}
+
+impl<M: Decode> Framer<M> {
+    pub fn new() -> Framer<M> {
+        Framer { buffer: Vec::with_capacity(M::SIZE), message: PhantomData }
+    }
+
+    /// Adds a value and returns the message it completes, if it does.
+    pub fn push(&mut self, value: MemoryValueType) -> Result<Option<M>> {
+        self.buffer.push(value);
+        if self.buffer.len() < M::SIZE {
+            return Ok(None);
+        }
+        let message = M::decode(&self.buffer);
+        self.buffer.clear();
+        message.map(Some)
+    }
+
+    /// Values of a message that is not complete yet.
+    pub fn pending(&self) -> &[MemoryValueType] {
+        &self.buffer
+    }
+}
+
+/// Groups the outputs of an iterator like `Outputs` into messages.
+///
+/// A value that does not decode only skips its message, any other error ends
+/// the iteration after it has been returned.
+///
+/// ```
+/// use intcode_computer::framing::Frames;
+/// use intcode_computer::outputs::Outputs;
+/// use intcode_computer::virtual_machine::VirtualMachine;
+///
+/// let vm = VirtualMachine::new(&[104,1, 104,2, 104,3, 104,4, 104,5, 104,6, 99]).unwrap();
+/// let tiles: Vec<(i64, i64, i64)> = Frames::new(Outputs::new(vm, None)).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(tiles, vec![(1, 2, 3), (4, 5, 6)]);
+/// ```
+pub struct Frames<I, M> {
+    outputs: I,
+    framer: Framer<M>,
+    done: bool,
+}
+
+impl<I, M> Frames<I, M>
+where
+    I: Iterator<Item = error::Result<MemoryValueType>>,
+    M: Decode,
+{
+    pub fn new(outputs: I) -> Frames<I, M> {
+        Frames { outputs, framer: Framer::new(), done: false }
+    }
+
+    fn advance(&mut self) -> Result<Option<M>> {
+        for value in &mut self.outputs {
+            if let Some(message) = self.framer.push(value?)? {
+                return Ok(Some(message));
+            }
+        }
+        match self.framer.pending().len() {
+            0 => Ok(None),
+            pending => Err(DecodeError::Truncated(pending)),
+        }
+    }
+}
+
+impl<I, M> Iterator for Frames<I, M>
+where
+    I: Iterator<Item = error::Result<MemoryValueType>>,
+    M: Decode,
+{
+    type Item = Result<M>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.advance() {
+            Ok(Some(message)) => Some(Ok(message)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err @ DecodeError::InvalidValue { .. }) => Some(Err(err)),
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::Outputs;
+    use crate::virtual_machine::VirtualMachine;
+    use num_enum::TryFromPrimitive;
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+    #[repr(i64)]
+    enum Color {
+        Black = 0,
+        White = 1,
+    }
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct Paint {
+        color: Color,
+        amount: MemoryValueType,
+    }
+
+    impl Decode for Paint {
+        const SIZE: usize = 2;
+
+        fn decode(values: &[MemoryValueType]) -> Result<Paint> {
+            Ok(Paint { color: field("color", values[0])?, amount: values[1] })
+        }
+    }
+
+    #[test]
+    fn test_framer() {
+        let mut framer = Framer::<Paint>::new();
+        assert_eq!(framer.push(1).unwrap(), None);
+        assert_eq!(framer.pending(), &[1]);
+        assert_eq!(framer.push(5).unwrap(), Some(Paint { color: Color::White, amount: 5 }));
+        assert!(framer.pending().is_empty());
+
+        framer.push(2).unwrap();
+        assert_eq!(framer.push(5).unwrap_err().to_string(), "Invalid color 2");
+        assert_eq!(framer.push(0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_frames() {
+        let vm = VirtualMachine::new(&[104,0, 104,3, 104,7, 104,3, 104,1, 104,4, 104,1, 99]).unwrap();
+        let mut paints = Frames::<_, Paint>::new(Outputs::new(vm, None));
+        assert_eq!(paints.next().unwrap().unwrap(), Paint { color: Color::Black, amount: 3 });
+        assert_eq!(paints.next().unwrap().unwrap_err().to_string(), "Invalid color 7");
+        assert_eq!(paints.next().unwrap().unwrap(), Paint { color: Color::White, amount: 4 });
+        assert_eq!(paints.next().unwrap().unwrap_err().to_string(), "Output ended after 1 values of a message");
+        assert!(paints.next().is_none());
+
+        let vm = VirtualMachine::new(&[104,0, 3,0, 99]).unwrap();
+        let mut paints = Frames::<_, Paint>::new(Outputs::new(vm, None));
+        paints.next().unwrap().expect_err("the machine has no input");
+        assert!(paints.next().is_none());
+    }
+}
@@ -0,0 +1,461 @@
+use crate::cfg::{ControlFlowGraph, Terminator};
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, ParameterMode};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+const NEG_INF: MemoryValueType = MemoryValueType::min_value();
+const POS_INF: MemoryValueType = MemoryValueType::max_value();
+
+/// Visits of a block before its entry state is widened instead of joined.
+const WIDENING_DELAY: usize = 3;
+
+/// Closed range of values. Bounds saturate at the limits of
+/// `MemoryValueType`, which stand for infinity.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Interval {
+    pub lo: MemoryValueType,
+    pub hi: MemoryValueType,
+}
+
+impl Interval {
+    pub fn new(lo: MemoryValueType, hi: MemoryValueType) -> Interval {
+        Interval { lo, hi }
+    }
+
+    pub fn constant(val: MemoryValueType) -> Interval {
+        Interval { lo: val, hi: val }
+    }
+
+    pub fn top() -> Interval {
+        Interval { lo: NEG_INF, hi: POS_INF }
+    }
+
+    pub fn as_constant(&self) -> Option<MemoryValueType> {
+        if self.lo == self.hi { Some(self.lo) } else { None }
+    }
+
+    pub fn contains(&self, val: MemoryValueType) -> bool {
+        self.lo <= val && val <= self.hi
+    }
+
+    pub fn join(&self, other: &Interval) -> Interval {
+        Interval { lo: self.lo.min(other.lo), hi: self.hi.max(other.hi) }
+    }
+
+    /// Like `join`, but bounds that keep growing jump to infinity.
+    pub fn widen(&self, next: &Interval) -> Interval {
+        Interval {
+            lo: if next.lo < self.lo { NEG_INF } else { self.lo },
+            hi: if next.hi > self.hi { POS_INF } else { self.hi },
+        }
+    }
+
+    fn add(&self, other: &Interval) -> Interval {
+        let lo = if self.lo == NEG_INF || other.lo == NEG_INF { NEG_INF } else { self.lo.saturating_add(other.lo) };
+        let hi = if self.hi == POS_INF || other.hi == POS_INF { POS_INF } else { self.hi.saturating_add(other.hi) };
+        Interval { lo, hi }
+    }
+
+    fn mul(&self, other: &Interval) -> Interval {
+        let products = [
+            self.lo.saturating_mul(other.lo),
+            self.lo.saturating_mul(other.hi),
+            self.hi.saturating_mul(other.lo),
+            self.hi.saturating_mul(other.hi),
+        ];
+        Interval {
+            lo: *products.iter().min().unwrap(),
+            hi: *products.iter().max().unwrap(),
+        }
+    }
+
+    fn less_than(&self, other: &Interval) -> Interval {
+        if self.hi < other.lo {
+            Interval::constant(1)
+        } else if self.lo >= other.hi {
+            Interval::constant(0)
+        } else {
+            Interval::new(0, 1)
+        }
+    }
+
+    fn equals(&self, other: &Interval) -> Interval {
+        match (self.as_constant(), other.as_constant()) {
+            (Some(x), Some(y)) if x == y => Interval::constant(1),
+            _ if self.hi < other.lo || other.hi < self.lo => Interval::constant(0),
+            _ => Interval::new(0, 1),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bound = |val| match val {
+            NEG_INF => "-inf".to_string(),
+            POS_INF => "inf".to_string(),
+            val => val.to_string(),
+        };
+        write!(f, "[{}, {}]", bound(self.lo), bound(self.hi))
+    }
+}
+
+/// Abstract memory: explicitly tracked cells plus one interval for all others.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct AbstractMemory {
+    cells: BTreeMap<usize, Interval>,
+    rest: Interval,
+}
+
+impl AbstractMemory {
+    fn load(&self, address: usize) -> Interval {
+        self.cells.get(&address).cloned().unwrap_or(self.rest)
+    }
+
+    /// Join of all cells the (non-negative part of the) range may refer to.
+    fn load_range(&self, range: &Interval) -> Option<Interval> {
+        let (lo, hi) = clamp_addresses(range)?;
+        let tracked: Vec<Interval> = self.cells.range(lo..=hi).map(|(_, v)| *v).collect();
+        let untracked = (hi - lo) as u128 + 1 > tracked.len() as u128;
+        tracked.into_iter()
+            .chain(if untracked { Some(self.rest) } else { None })
+            .fold(None, |acc: Option<Interval>, v| Some(acc.map(|a| a.join(&v)).unwrap_or(v)))
+    }
+
+    fn store(&mut self, range: &Interval, value: Interval) {
+        let (lo, hi) = match clamp_addresses(range) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        if lo == hi {
+            self.cells.insert(lo, value);
+            return;
+        }
+        let mut tracked = 0u128;
+        for cell in self.cells.range_mut(lo..=hi) {
+            *cell.1 = cell.1.join(&value);
+            tracked += 1;
+        }
+        if (hi - lo) as u128 + 1 > tracked {
+            self.rest = self.rest.join(&value);
+        }
+    }
+
+    fn combine(&self, other: &AbstractMemory, f: impl Fn(&Interval, &Interval) -> Interval) -> AbstractMemory {
+        let addresses: BTreeSet<usize> = self.cells.keys().chain(other.cells.keys()).cloned().collect();
+        AbstractMemory {
+            cells: addresses.into_iter().map(|a| (a, f(&self.load(a), &other.load(a)))).collect(),
+            rest: f(&self.rest, &other.rest),
+        }
+    }
+}
+
+/// Non-negative part of an address range, as only that can be accessed.
+fn clamp_addresses(range: &Interval) -> Option<(usize, usize)> {
+    if range.hi < 0 {
+        None
+    } else {
+        Some((range.lo.max(0) as usize, range.hi as usize))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct State {
+    relative_base: Interval,
+    memory: AbstractMemory,
+}
+
+impl State {
+    fn combine(&self, other: &State, f: impl Fn(&Interval, &Interval) -> Interval) -> State {
+        State {
+            relative_base: f(&self.relative_base, &other.relative_base),
+            memory: self.memory.combine(&other.memory, f),
+        }
+    }
+}
+
+/// What the analysis knows about one instruction, over all executions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InstructionFacts {
+    pub relative_base: Interval,
+    /// Addresses each parameter may access, `None` for immediates
+    pub addresses: Vec<Option<Interval>>,
+}
+
+impl InstructionFacts {
+    fn join(&self, other: &InstructionFacts) -> InstructionFacts {
+        InstructionFacts {
+            relative_base: self.relative_base.join(&other.relative_base),
+            addresses: self.addresses.iter().zip(&other.addresses).map(|(a, b)| match (a, b) {
+                (Some(a), Some(b)) => Some(a.join(b)),
+                _ => None,
+            }).collect(),
+        }
+    }
+}
+
+/// Interval analysis over the recovered control flow graph.
+///
+/// Computes, for every reachable instruction, the possible values of the
+/// relative base and the addresses its parameters may access, plus the
+/// program cells that are never changed. Operands are read from the abstract
+/// memory, so programs that modify their own operands are handled. The results
+/// only cover paths within the control flow graph: check `is_complete` before
+/// relying on them.
+pub struct RangeAnalysis {
+    program: Vec<MemoryValueType>,
+    facts: BTreeMap<usize, InstructionFacts>,
+    // (address, value) of all stores per instruction
+    writes: HashMap<usize, (Interval, Interval)>,
+    unresolved_jumps: BTreeSet<usize>,
+    modified_instructions: BTreeSet<usize>,
+}
+
+impl RangeAnalysis {
+    pub fn analyze(program: &[MemoryValueType]) -> RangeAnalysis {
+        let cfg = ControlFlowGraph::recover(program);
+        let mut analysis = RangeAnalysis {
+            program: program.to_vec(),
+            facts: BTreeMap::new(),
+            writes: HashMap::new(),
+            unresolved_jumps: BTreeSet::new(),
+            modified_instructions: BTreeSet::new(),
+        };
+
+        let initial = State {
+            relative_base: Interval::constant(0),
+            memory: AbstractMemory {
+                cells: program.iter().enumerate().map(|(i, v)| (i, Interval::constant(*v))).collect(),
+                rest: Interval::constant(0),
+            },
+        };
+        let mut entries: HashMap<usize, State> = HashMap::new();
+        let mut visits: HashMap<usize, usize> = HashMap::new();
+        entries.insert(0, initial);
+        let mut worklist = vec![0];
+
+        while let Some(start) = worklist.pop() {
+            let block = match cfg.block(start) {
+                Some(block) => block,
+                None => continue,
+            };
+            let mut state = entries[&start].clone();
+            let mut successors = Vec::new();
+
+            for instruction in &block.instructions {
+                let pc = instruction.address;
+                let word = program[pc];
+                if state.memory.load(pc) != Interval::constant(word) {
+                    analysis.modified_instructions.insert(pc);
+                }
+
+                let mut addresses = Vec::new();
+                let mut values = Vec::new();
+                for (i, mode) in instruction.modes.iter().take(instruction.params.len()).enumerate() {
+                    let raw = state.memory.load(pc + i + 1);
+                    let address = match mode {
+                        ParameterMode::Immediate => None,
+                        ParameterMode::Position => Some(raw),
+                        ParameterMode::Relative => Some(state.relative_base.add(&raw)),
+                    };
+                    values.push(match address {
+                        None => Some(raw),
+                        Some(address) => state.memory.load_range(&address),
+                    });
+                    addresses.push(address);
+                }
+                let facts = InstructionFacts { relative_base: state.relative_base, addresses: addresses.clone() };
+                let joined = match analysis.facts.get(&pc) {
+                    Some(old) => old.join(&facts),
+                    None => facts,
+                };
+                analysis.facts.insert(pc, joined);
+
+                // a value of `None` means every access fails, so the path ends
+                let binary = |f: fn(&Interval, &Interval) -> Interval| match (values[0], values[1]) {
+                    (Some(a), Some(b)) => Some(f(&a, &b)),
+                    _ => None,
+                };
+                let result = match instruction.opcode {
+                    Opcode::Add => binary(Interval::add),
+                    Opcode::Mul => binary(Interval::mul),
+                    Opcode::LT => binary(Interval::less_than),
+                    Opcode::EQ => binary(Interval::equals),
+                    Opcode::In => Some(Interval::top()),
+                    _ => None,
+                };
+                if let (Some(value), Some(destination)) = (result, instruction.opcode.destination()) {
+                    if let Some(address) = addresses[destination] {
+                        state.memory.store(&address, value);
+                        let write = analysis.writes.entry(pc).or_insert((address, value));
+                        *write = (write.0.join(&address), write.1.join(&value));
+                    }
+                }
+
+                let completed = match instruction.opcode {
+                    Opcode::Add | Opcode::Mul | Opcode::LT | Opcode::EQ | Opcode::In => result.is_some(),
+                    Opcode::Out => values[0].is_some(),
+                    Opcode::RBO => match values[0] {
+                        Some(offset) => {
+                            let base = state.relative_base.add(&offset);
+                            state.relative_base = Interval::new(base.lo.max(0), base.hi);
+                            base.hi >= 0
+                        }
+                        None => false,
+                    },
+                    Opcode::JNZ | Opcode::JZ => match values[0] {
+                        Some(condition) => {
+                            let zero = condition.contains(0);
+                            let non_zero = condition != Interval::constant(0);
+                            let (jumps, falls) = if instruction.opcode == Opcode::JNZ {
+                                (non_zero, zero)
+                            } else {
+                                (zero, non_zero)
+                            };
+                            // the target is only read when the jump is taken
+                            if jumps {
+                                match values[1].and_then(|target| target.as_constant()) {
+                                    Some(t) if t >= 0 && cfg.block(t as usize).is_some() => successors.push(t as usize),
+                                    _ if values[1].is_none() => (),
+                                    _ => { analysis.unresolved_jumps.insert(pc); },
+                                }
+                            }
+                            if falls {
+                                successors.push(pc + instruction.length());
+                            }
+                            true
+                        }
+                        None => false,
+                    },
+                    Opcode::Halt => false,
+                };
+                if !completed {
+                    successors.clear();
+                    break;
+                }
+                if instruction.address == block.instructions.last().unwrap().address {
+                    if let Terminator::FallThrough(next) = block.terminator {
+                        successors.push(next);
+                    }
+                }
+            }
+
+            for successor in successors {
+                let count = visits.entry(successor).or_insert(0);
+                *count += 1;
+                let next = match entries.get(&successor) {
+                    None => state.clone(),
+                    Some(old) if *count > WIDENING_DELAY => old.combine(&old.combine(&state, Interval::join), Interval::widen),
+                    Some(old) => old.combine(&state, Interval::join),
+                };
+                if entries.get(&successor) != Some(&next) {
+                    entries.insert(successor, next);
+                    worklist.push(successor);
+                }
+            }
+        }
+
+        analysis
+    }
+
+    pub fn facts(&self, address: usize) -> Option<&InstructionFacts> {
+        self.facts.get(&address)
+    }
+
+    /// Addresses of all instructions reached by the analysis.
+    pub fn reached(&self) -> impl Iterator<Item = usize> + '_ {
+        self.facts.keys().cloned()
+    }
+
+    /// Whether the instruction can never access a negative address.
+    pub fn never_negative(&self, address: usize) -> bool {
+        self.facts.get(&address).map(|facts| {
+            facts.addresses.iter().all(|a| a.map(|a| a.lo >= 0).unwrap_or(true))
+        }).unwrap_or(true)
+    }
+
+    /// Jumps whose target could not be narrowed down to a known block.
+    pub fn unresolved_jumps(&self) -> impl Iterator<Item = usize> + '_ {
+        self.unresolved_jumps.iter().cloned()
+    }
+
+    /// Instruction words that may be overwritten before they are executed.
+    pub fn modified_instructions(&self) -> impl Iterator<Item = usize> + '_ {
+        self.modified_instructions.iter().cloned()
+    }
+
+    /// Whether the results hold for every execution of the program.
+    pub fn is_complete(&self) -> bool {
+        self.unresolved_jumps.is_empty() && self.modified_instructions.is_empty()
+    }
+
+    /// Program cells that keep their initial value during every execution.
+    pub fn constants(&self) -> BTreeMap<usize, MemoryValueType> {
+        self.program.iter().cloned().enumerate().filter(|&(address, initial)| {
+            self.writes.values().all(|(addresses, value)| {
+                !addresses.contains(address as MemoryValueType) || *value == Interval::constant(initial)
+            })
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_arithmetic() {
+        let a = Interval::new(-2, 3);
+        let b = Interval::new(4, 5);
+        assert_eq!(a.add(&b), Interval::new(2, 8));
+        assert_eq!(a.mul(&b), Interval::new(-10, 15));
+        assert_eq!(a.less_than(&b), Interval::constant(1));
+        assert_eq!(b.less_than(&a), Interval::constant(0));
+        assert_eq!(a.equals(&b), Interval::constant(0));
+        assert_eq!(a.equals(&a), Interval::new(0, 1));
+        assert_eq!(a.widen(&Interval::new(-2, 4)), Interval::new(-2, POS_INF));
+        assert_eq!(Interval::top().add(&b), Interval::top());
+        assert_eq!(Interval::new(-2, POS_INF).to_string(), "[-2, inf]");
+    }
+
+    #[test]
+    fn test_loop() {
+        // i = 0; do { i += 1 } while i < 10; out(i) with i at [rb+0] = 100
+        let program = vec![109,100,21101,0,0,0,22101,1,0,0,21207,0,10,1,1205,1,6,204,0,99];
+        let analysis = RangeAnalysis::analyze(&program);
+
+        assert!(analysis.is_complete());
+        assert_eq!(analysis.reached().collect::<Vec<_>>(), vec![0, 2, 6, 10, 14, 17, 19]);
+        assert!(analysis.reached().all(|address| analysis.never_negative(address)));
+        let facts = analysis.facts(6).unwrap();
+        assert_eq!(facts.relative_base, Interval::constant(100));
+        assert_eq!(facts.addresses, vec![None, Some(Interval::constant(100)), Some(Interval::constant(100))]);
+        assert_eq!(analysis.constants().len(), program.len());
+    }
+
+    #[test]
+    fn test_self_modification() {
+        // day 05 example: the input overwrites the condition of the jump
+        let program = vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1];
+        let analysis = RangeAnalysis::analyze(&program);
+        assert!(analysis.is_complete());
+        assert_eq!(analysis.reached().collect::<Vec<_>>(), vec![0, 2, 5, 9, 11]);
+        let constants = analysis.constants();
+        assert!(!constants.contains_key(&3));
+        assert!(!constants.contains_key(&12));
+        assert_eq!(constants.len(), program.len() - 2);
+    }
+
+    #[test]
+    fn test_negative_addresses() {
+        // reads from [rb-6] with the relative base at 5
+        let analysis = RangeAnalysis::analyze(&[109,5,1201,-6,0,10,99]);
+        assert!(analysis.never_negative(0));
+        assert!(!analysis.never_negative(2));
+        assert_eq!(analysis.reached().collect::<Vec<_>>(), vec![0, 2]);
+
+        let analysis = RangeAnalysis::analyze(&[2106,0,0]);
+        assert_eq!(analysis.unresolved_jumps().collect::<Vec<_>>(), vec![0]);
+        assert!(!analysis.is_complete());
+    }
+}
@@ -2,4 +2,28 @@ pub mod error;
 pub mod virtual_machine;
 pub mod opcode;
 pub mod util;
-pub mod memory;
\ No newline at end of file
+pub mod memory;
+pub mod lint;
+pub mod taint;
+pub mod symbolic;
+pub mod cfg;
+pub mod interval;
+pub mod slice;
+pub mod loader;
+pub mod container;
+pub mod linker;
+pub mod assembler;
+pub mod stdlib;
+pub mod compiler;
+pub mod sourcemap;
+pub mod debugger;
+pub mod async_machine;
+pub mod pipeline;
+pub mod topology;
+pub mod network;
+pub mod outputs;
+pub mod device;
+pub mod framing;
+pub mod ascii;
+#[cfg(test)]
+mod fixtures;
\ No newline at end of file
@@ -0,0 +1,426 @@
+use crate::cfg::ControlFlowGraph;
+use crate::error::LinkError;
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, ParameterMode};
+use crate::sourcemap::{DebugInfo, Location, SourceMap};
+use crate::util::string_to_program;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+
+pub type Result<T> = std::result::Result<T, LinkError>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    /// Position of the symbol relative to the start of its module
+    pub offset: usize,
+    /// Whether other modules can reference the symbol
+    pub global: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    /// The word holds an address relative to the start of its own module
+    Local,
+    /// The word is replaced by the address of a global symbol plus the addend
+    External { symbol: String, addend: MemoryValueType },
+}
+
+/// A word that has to be patched once the final addresses are known.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Relocation {
+    pub offset: usize,
+    pub target: Target,
+}
+
+/// A relocatable module: code assembled as if it started at address 0.
+///
+/// The text representation is a header of directives followed by `code` and
+/// the program:
+///
+/// ```text
+/// module main
+/// global start 0
+/// local loop 4
+/// reloc 2
+/// extern 5 value 0
+/// source 0 4 12 main.asm
+/// code
+/// 1105,1,4,99,4,0,99
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Object {
+    pub name: String,
+    pub code: Vec<MemoryValueType>,
+    pub symbols: Vec<Symbol>,
+    pub relocations: Vec<Relocation>,
+    /// Source lines of the code, relative to the start of the module
+    pub source: SourceMap,
+}
+
+impl Object {
+    pub fn new(name: &str, code: Vec<MemoryValueType>) -> Object {
+        Object { name: name.to_string(), code, symbols: Vec::new(), relocations: Vec::new(), source: SourceMap::default() }
+    }
+
+    /// Turns a plain program into a module by relocating every position mode
+    /// operand and immediate jump target of the statically reachable code.
+    /// Addresses stored in data words or computed at runtime (e.g. through the
+    /// relative base) can not be found this way.
+    pub fn relocatable(name: &str, program: &[MemoryValueType]) -> Object {
+        let mut offsets = BTreeSet::new();
+        let cfg = ControlFlowGraph::recover(program);
+        for instruction in cfg.blocks().flat_map(|block| block.instructions.iter()) {
+            let is_jump = instruction.opcode == Opcode::JNZ || instruction.opcode == Opcode::JZ;
+            for (i, &param) in instruction.params.iter().enumerate() {
+                let relocate = match instruction.modes[i] {
+                    ParameterMode::Position => param >= 0,
+                    ParameterMode::Immediate => is_jump && i == 1 && param >= 0,
+                    ParameterMode::Relative => false,
+                };
+                if relocate && instruction.address + i + 1 < program.len() {
+                    offsets.insert(instruction.address + i + 1);
+                }
+            }
+        }
+        let mut object = Object::new(name, program.to_vec());
+        for offset in offsets {
+            object.relocate(offset);
+        }
+        object
+    }
+
+    pub fn define(&mut self, name: &str, offset: usize, global: bool) -> &mut Self {
+        self.symbols.push(Symbol { name: name.to_string(), offset, global });
+        self
+    }
+
+    /// Marks the word at the offset as holding a module relative address.
+    pub fn relocate(&mut self, offset: usize) -> &mut Self {
+        self.relocations.push(Relocation { offset, target: Target::Local });
+        self
+    }
+
+    /// Marks the word at the offset as referencing a global symbol.
+    pub fn reference(&mut self, offset: usize, symbol: &str, addend: MemoryValueType) -> &mut Self {
+        let target = Target::External { symbol: symbol.to_string(), addend };
+        self.relocations.push(Relocation { offset, target });
+        self
+    }
+
+    pub fn parse(s: &str) -> Result<Object> {
+        let mut object = Object::new("", Vec::new());
+        let mut lines = s.lines().enumerate();
+        for (i, line) in &mut lines {
+            let syntax = |message: &str| LinkError::Syntax { line: i + 1, message: message.to_string() };
+            let offset = |token: &str| token.parse::<usize>().map_err(|_| syntax("invalid offset"));
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                [] => (),
+                ["module", name] => object.name = name.to_string(),
+                ["global", name, at] => { object.define(name, offset(at)?, true); }
+                ["local", name, at] => { object.define(name, offset(at)?, false); }
+                ["reloc", at] => { object.relocate(offset(at)?); }
+                ["extern", at, symbol] => { object.reference(offset(at)?, symbol, 0); }
+                ["extern", at, symbol, addend] => {
+                    let addend = addend.parse().map_err(|_| syntax("invalid addend"))?;
+                    object.reference(offset(at)?, symbol, addend);
+                }
+                ["source", start, end, line, file] => {
+                    let line = line.parse().map_err(|_| syntax("invalid line"))?;
+                    object.source.insert(offset(start)?..offset(end)?, Location { file: file.to_string(), line });
+                }
+                ["code"] => {
+                    let code: Vec<&str> = lines.map(|(_, line)| line).collect();
+                    object.code = string_to_program(&code.join("\n"))?;
+                    return Ok(object);
+                }
+                _ => return Err(syntax("unknown directive")),
+            }
+        }
+        Err(LinkError::Syntax { line: s.lines().count(), message: "missing `code`".to_string() })
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "module {}", self.name)?;
+        for symbol in &self.symbols {
+            writeln!(f, "{} {} {}", if symbol.global { "global" } else { "local" }, symbol.name, symbol.offset)?;
+        }
+        for relocation in &self.relocations {
+            match &relocation.target {
+                Target::Local => writeln!(f, "reloc {}", relocation.offset)?,
+                Target::External { symbol, addend } => writeln!(f, "extern {} {} {}", relocation.offset, symbol, addend)?,
+            }
+        }
+        for (range, location) in self.source.ranges() {
+            writeln!(f, "source {} {} {} {}", range.start, range.end, location.line, location.file)?;
+        }
+        writeln!(f, "code")?;
+        let code: Vec<String> = self.code.iter().map(|v| v.to_string()).collect();
+        writeln!(f, "{}", code.join(","))
+    }
+}
+
+/// Final addresses of all symbols and modules of a linked image. Local
+/// symbols are qualified with their module, e.g. `main::loop`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SymbolMap {
+    symbols: BTreeMap<String, usize>,
+    modules: Vec<(String, Range<usize>)>,
+}
+
+impl SymbolMap {
+    pub fn address(&self, name: &str) -> Option<usize> {
+        self.symbols.get(name).cloned()
+    }
+
+    /// Closest symbol at or before the address and the distance to it.
+    /// Global symbols win over local ones at the same address.
+    pub fn symbolize(&self, address: usize) -> Option<(&str, usize)> {
+        self.symbols.iter()
+            .filter(|(_, &at)| at <= address)
+            .max_by_key(|(name, &at)| (at, !name.contains("::")))
+            .map(|(name, &at)| (name.as_str(), address - at))
+    }
+
+    /// Name of the module the address belongs to.
+    pub fn module(&self, address: usize) -> Option<&str> {
+        self.modules.iter()
+            .find(|(_, range)| range.contains(&address))
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.symbols.iter().map(|(name, &at)| (name.as_str(), at))
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = (&str, Range<usize>)> {
+        self.modules.iter().map(|(name, range)| (name.as_str(), range.clone()))
+    }
+
+    /// Reads the format written by `Display`.
+    pub fn parse(s: &str) -> Result<SymbolMap> {
+        let mut map = SymbolMap::default();
+        for (i, line) in s.lines().enumerate() {
+            let syntax = || LinkError::Syntax { line: i + 1, message: "invalid symbol map entry".to_string() };
+            let number = |token: &str| token.parse::<usize>().map_err(|_| syntax());
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => (),
+                ["module", name, start, end] => map.modules.push((name.to_string(), number(start)?..number(end)?)),
+                [address, name] => { map.symbols.insert(name.to_string(), number(address)?); }
+                _ => return Err(syntax()),
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl fmt::Display for SymbolMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, range) in &self.modules {
+            writeln!(f, "module {} {} {}", name, range.start, range.end)?;
+        }
+        let mut symbols: Vec<(&usize, &String)> = self.symbols.iter().map(|(name, at)| (at, name)).collect();
+        symbols.sort();
+        for (address, name) in symbols {
+            writeln!(f, "{} {}", address, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// A linked program ready to be loaded into a `VirtualMachine`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Image {
+    pub program: Vec<MemoryValueType>,
+    pub symbols: SymbolMap,
+    pub source: SourceMap,
+}
+
+impl Image {
+    pub fn debug_info(&self) -> DebugInfo {
+        DebugInfo { symbols: self.symbols.clone(), source: self.source.clone() }
+    }
+}
+
+/// Lays out modules one after the other in the order they were added.
+///
+/// Execution starts at address 0, so without an entry symbol the first module
+/// has to begin with its entry point. With an entry symbol, the image starts
+/// with a jump to it.
+#[derive(Clone, Debug, Default)]
+pub struct Linker {
+    objects: Vec<Object>,
+    entry: Option<String>,
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker::default()
+    }
+
+    pub fn add(&mut self, object: Object) -> &mut Self {
+        self.objects.push(object);
+        self
+    }
+
+    pub fn entry(&mut self, symbol: &str) -> &mut Self {
+        self.entry = Some(symbol.to_string());
+        self
+    }
+
+    pub fn link(&self) -> Result<Image> {
+        let stub = if self.entry.is_some() { 3 } else { 0 };
+        let mut map = SymbolMap::default();
+        let mut globals: BTreeMap<&str, (usize, &str)> = BTreeMap::new();
+
+        let mut base = stub;
+        for object in &self.objects {
+            for symbol in &object.symbols {
+                if symbol.offset > object.code.len() {
+                    return Err(LinkError::OutOfRange { module: object.name.clone(), offset: symbol.offset });
+                }
+                let address = base + symbol.offset;
+                if symbol.global {
+                    if let Some((_, first)) = globals.insert(&symbol.name, (address, &object.name)) {
+                        return Err(LinkError::DuplicateSymbol {
+                            name: symbol.name.clone(),
+                            first: first.to_string(),
+                            second: object.name.clone(),
+                        });
+                    }
+                    map.symbols.insert(symbol.name.clone(), address);
+                } else {
+                    map.symbols.insert(format!("{}::{}", object.name, symbol.name), address);
+                }
+            }
+            map.modules.push((object.name.clone(), base..base + object.code.len()));
+            base += object.code.len();
+        }
+
+        let mut program = Vec::with_capacity(base);
+        let mut source = SourceMap::default();
+        if let Some(entry) = &self.entry {
+            let undefined = || LinkError::UndefinedSymbol { name: entry.clone(), module: "<entry>".to_string() };
+            let (address, _) = globals.get(entry.as_str()).ok_or_else(undefined)?;
+            program.extend_from_slice(&[1105, 1, *address as MemoryValueType]);
+        }
+        for object in &self.objects {
+            let base = program.len();
+            program.extend_from_slice(&object.code);
+            source.extend(object.source.shifted(base));
+            for relocation in &object.relocations {
+                if relocation.offset >= object.code.len() {
+                    return Err(LinkError::OutOfRange { module: object.name.clone(), offset: relocation.offset });
+                }
+                let word = &mut program[base + relocation.offset];
+                match &relocation.target {
+                    Target::Local => *word += base as MemoryValueType,
+                    Target::External { symbol, addend } => {
+                        let (address, _) = globals.get(symbol.as_str()).ok_or_else(|| {
+                            LinkError::UndefinedSymbol { name: symbol.clone(), module: object.name.clone() }
+                        })?;
+                        *word = *address as MemoryValueType + addend;
+                    }
+                }
+            }
+        }
+        Ok(Image { program, symbols: map, source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_machine::{VirtualMachine, VMState};
+
+    const MAIN: &str = "\
+# jumps over a halt and prints `value`
+module main
+global start 0
+local print 4
+reloc 2
+extern 5 value
+code
+1105,1,4,99,
+4,0,99
+";
+
+    fn run(program: &[MemoryValueType]) -> MemoryValueType {
+        let mut vm = VirtualMachine::new(program).unwrap();
+        assert_eq!(vm.run().unwrap(), VMState::Blocked);
+        vm.output().unwrap()
+    }
+
+    #[test]
+    fn test_link() {
+        let main = Object::parse(MAIN).unwrap();
+        assert_eq!(Object::parse(&main.to_string()).unwrap(), main);
+        let mut data = Object::new("data", vec![7, 42]);
+        data.define("value", 1, true);
+
+        let image = Linker::new().add(main.clone()).add(data.clone()).link().unwrap();
+        assert_eq!(image.program, vec![1105,1,4,99,4,8,99,7,42]);
+        assert_eq!(run(&image.program), 42);
+
+        let image = Linker::new().add(data).add(main).entry("start").link().unwrap();
+        assert_eq!(image.program, vec![1105,1,5,7,42,1105,1,9,99,4,4,99]);
+        assert_eq!(run(&image.program), 42);
+
+        let symbols = &image.symbols;
+        assert_eq!(symbols.address("value"), Some(4));
+        assert_eq!(symbols.address("main::print"), Some(9));
+        assert_eq!(symbols.symbolize(6), Some(("start", 1)));
+        assert_eq!(symbols.symbolize(10), Some(("main::print", 1)));
+        assert_eq!(symbols.symbolize(1), None);
+        assert_eq!(symbols.module(3), Some("data"));
+        assert_eq!(symbols.module(11), Some("main"));
+        assert_eq!(SymbolMap::parse(&symbols.to_string()).unwrap(), *symbols);
+    }
+
+    #[test]
+    fn test_errors() {
+        let main = Object::parse(MAIN).unwrap();
+        match Linker::new().add(main.clone()).link() {
+            Err(LinkError::UndefinedSymbol { name, module }) => assert_eq!((name.as_str(), module.as_str()), ("value", "main")),
+            other => panic!("unexpected {:?}", other),
+        }
+        let mut other = Object::new("other", vec![0]);
+        other.define("start", 0, true);
+        match Linker::new().add(main).add(other).link() {
+            Err(LinkError::DuplicateSymbol { name, .. }) => assert_eq!(name, "start"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match Object::parse("module a\nfoo 1\ncode\n99") {
+            Err(LinkError::Syntax { line: 2, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_relocatable() {
+        // day 05 example: outputs whether the input is non-zero. The jump
+        // target is read from the data word at 15, which is not relocated.
+        let program = vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9];
+        let object = Object::relocatable("compare", &program);
+        let offsets: Vec<usize> = object.relocations.iter().map(|r| r.offset).collect();
+        assert_eq!(offsets, vec![1, 3, 4, 6, 7, 8, 10]);
+
+        let image = Linker::new().add(Object::new("padding", vec![0; 5])).add(object).entry("main").link();
+        assert!(image.is_err());
+
+        let mut object = Object::relocatable("compare", &program);
+        object.define("main", 0, true);
+        let image = Linker::new().add(Object::new("padding", vec![0; 5])).add(object).entry("main").link().unwrap();
+        let mut vm = VirtualMachine::new(&image.program).unwrap();
+        vm.input(5).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.output().unwrap(), 1);
+    }
+}
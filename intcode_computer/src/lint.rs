@@ -0,0 +1,276 @@
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, ParameterMode, decode_opcode, decode_parameter_modes};
+use crate::sourcemap::DebugInfo;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    Warning,
+    Error
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single problem found by `lint`. Every variant corresponds to a stable,
+/// machine-readable code (see `LintKind::code`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LintKind {
+    InvalidOpcode { word: MemoryValueType },
+    InvalidParameterMode { word: MemoryValueType },
+    ImmediateDestination { parameter: usize },
+    TruncatedInstruction { length: usize, available: usize },
+    NegativeJumpTarget { target: MemoryValueType },
+    SelfModifyingWrite { target: usize, instruction: usize },
+}
+
+impl LintKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LintKind::InvalidOpcode { .. } => "invalid-opcode",
+            LintKind::InvalidParameterMode { .. } => "invalid-parameter-mode",
+            LintKind::ImmediateDestination { .. } => "immediate-destination",
+            LintKind::TruncatedInstruction { .. } => "truncated-instruction",
+            LintKind::NegativeJumpTarget { .. } => "negative-jump-target",
+            LintKind::SelfModifyingWrite { .. } => "self-modifying-write",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintKind::SelfModifyingWrite { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintKind::InvalidOpcode { word } => write!(f, "Word {} has no valid opcode", word),
+            LintKind::InvalidParameterMode { word } => write!(f, "Word {} has an invalid parameter mode", word),
+            LintKind::ImmediateDestination { parameter } => write!(f, "Destination parameter {} is immediate", parameter),
+            LintKind::TruncatedInstruction { length, available } =>
+                write!(f, "Instruction needs {} words but only {} are left in the program", length, available),
+            LintKind::NegativeJumpTarget { target } => write!(f, "Jump to negative address {}", target),
+            LintKind::SelfModifyingWrite { target, instruction } =>
+                write!(f, "Write to address {} modifies the instruction at {}", target, instruction),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub address: usize,
+    pub kind: LintKind,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+
+    /// The `Display` form with addresses shown as labels and source lines
+    /// where `info` knows them.
+    pub fn describe(&self, info: &DebugInfo) -> String {
+        let message = match self.kind {
+            LintKind::SelfModifyingWrite { target, instruction } => format!(
+                "Write to address {} modifies the instruction at {}",
+                info.describe(target),
+                info.describe(instruction)
+            ),
+            ref kind => kind.to_string(),
+        };
+        format!("{}:{}:{}:{}", info.describe(self.address), self.severity(), self.kind.code(), message)
+    }
+}
+
+/// One diagnostic per line as `<address>:<severity>:<code>:<message>`.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}", self.address, self.severity(), self.kind.code(), self.kind)
+    }
+}
+
+/// Checks a program for problems without running it.
+///
+/// Programs mix code and data, so only instructions reachable from address 0
+/// are inspected. Control flow is followed through fall-through and jumps with
+/// immediate targets; jumps whose target is only known at runtime end the
+/// traversal of that path. Diagnostics are sorted by address.
+pub fn lint(program: &[MemoryValueType]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    // (address, length) of every reachable, well formed instruction
+    let mut instructions = Vec::new();
+    // (instruction address, destination address) of constant writes
+    let mut writes = Vec::new();
+
+    let mut visited = HashSet::new();
+    let mut worklist = vec![0];
+
+    while let Some(address) = worklist.pop() {
+        if address >= program.len() || !visited.insert(address) {
+            continue;
+        }
+        let word = program[address];
+        let opcode = match decode_opcode(word) {
+            Ok(opcode) => opcode,
+            Err(_) => {
+                diagnostics.push(Diagnostic { address, kind: LintKind::InvalidOpcode { word } });
+                continue;
+            }
+        };
+        let modes = match decode_parameter_modes(word) {
+            Ok(modes) => modes,
+            Err(_) => {
+                diagnostics.push(Diagnostic { address, kind: LintKind::InvalidParameterMode { word } });
+                continue;
+            }
+        };
+
+        let length = opcode.parameter_count() + 1;
+        let available = program.len() - address;
+        if length > available {
+            diagnostics.push(Diagnostic { address, kind: LintKind::TruncatedInstruction { length, available } });
+            continue;
+        }
+        instructions.push((address, length));
+        let params = &program[address + 1..address + length];
+
+        if let Some(parameter) = opcode.destination() {
+            match modes[parameter] {
+                ParameterMode::Immediate => diagnostics.push(Diagnostic {
+                    address,
+                    kind: LintKind::ImmediateDestination { parameter }
+                }),
+                ParameterMode::Position if params[parameter] >= 0 => {
+                    writes.push((address, params[parameter] as usize));
+                }
+                _ => (),
+            }
+        }
+
+        let next = address + length;
+        match opcode {
+            Opcode::Halt => (),
+            Opcode::JNZ | Opcode::JZ => {
+                let condition = if modes[0] == ParameterMode::Immediate {
+                    Some(params[0] != 0)
+                } else {
+                    None
+                };
+                let taken = if opcode == Opcode::JNZ { condition } else { condition.map(|c| !c) };
+
+                if taken != Some(false) && modes[1] == ParameterMode::Immediate {
+                    if params[1] < 0 {
+                        diagnostics.push(Diagnostic {
+                            address,
+                            kind: LintKind::NegativeJumpTarget { target: params[1] }
+                        });
+                    } else {
+                        worklist.push(params[1] as usize);
+                    }
+                }
+                if taken != Some(true) {
+                    worklist.push(next);
+                }
+            }
+            _ => worklist.push(next),
+        }
+    }
+
+    let starts: BTreeMap<usize, usize> = instructions.into_iter().collect();
+    for (instruction, target) in writes {
+        // the closest instruction starting at or before the target
+        if let Some((&start, &length)) = starts.range(..=target).next_back() {
+            if target < start + length {
+                diagnostics.push(Diagnostic {
+                    address: instruction,
+                    kind: LintKind::SelfModifyingWrite { target, instruction: start }
+                });
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.address);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::linker::Linker;
+
+    fn codes(program: &[MemoryValueType]) -> Vec<(usize, &'static str)> {
+        lint(program).iter().map(|d| (d.address, d.kind.code())).collect()
+    }
+
+    #[test]
+    fn test_clean_program() {
+        // day 05 example: outputs 1 if the input equals 8
+        let program = vec![3,9,8,9,10,9,4,9,99,-1,8];
+        assert_eq!(lint(&program), vec![]);
+    }
+
+    #[test]
+    fn test_data_after_halt_is_ignored() {
+        let program = vec![1,7,8,9,4,9,99,30,40,0];
+        assert_eq!(lint(&program), vec![]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(codes(&[1,0,0]), vec![(0, "truncated-instruction")]);
+        assert_eq!(codes(&[11101,1,1,1,99]), vec![(0, "immediate-destination")]);
+        assert_eq!(codes(&[301,0,0,0,99]), vec![(0, "invalid-parameter-mode")]);
+        assert_eq!(codes(&[1101,1,1,7,42]), vec![(4, "invalid-opcode")]);
+        assert_eq!(codes(&[1105,1,-4]), vec![(0, "negative-jump-target")]);
+    }
+
+    #[test]
+    fn test_follows_jumps() {
+        // unconditional jump over garbage
+        assert_eq!(codes(&[1105,1,4,42,99]), vec![]);
+        // never taken jump to a garbage target
+        assert_eq!(codes(&[1106,1,3,99]), vec![]);
+    }
+
+    #[test]
+    fn test_self_modification() {
+        // day 02 example writes into its own operands and opcodes
+        let diagnostics = lint(&[1,9,10,3,2,3,11,0,99,30,40,50]);
+        assert_eq!(diagnostics, vec![
+            Diagnostic { address: 0, kind: LintKind::SelfModifyingWrite { target: 3, instruction: 0 } },
+            Diagnostic { address: 4, kind: LintKind::SelfModifyingWrite { target: 0, instruction: 0 } },
+        ]);
+        assert!(diagnostics.iter().all(|d| !d.is_error()));
+        assert_eq!(diagnostics[1].to_string(), "4:warning:self-modifying-write:Write to address 0 modifies the instruction at 0");
+    }
+
+    #[test]
+    fn test_describe() {
+        let source = "patch:  mov #99, done\ndone:   jmp #patch\n";
+        let mut linker = Linker::new();
+        linker.add(assemble("main", source).unwrap());
+        let image = linker.link().unwrap();
+        let diagnostics = lint(&image.program);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].describe(&image.debug_info()),
+            "main::patch (main:1):warning:self-modifying-write:\
+             Write to address main::done (main:2) modifies the instruction at main::done (main:2)"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error::{LoadError, ParseError};
+use crate::memory::MemoryValueType;
+use crate::util::string_to_program;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{Read, Write};
+use std::path::Path;
+
+pub type Result<T> = std::result::Result<T, LoadError>;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const BINARY_MAGIC: &[u8] = b"ICB1";
+
+/// On-disk representations of a program.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// Comma separated values, the puzzle input format
+    Text,
+    /// A JSON array of integers
+    Json,
+    /// One value per line
+    Lines,
+    /// `ICB1` followed by the number of values and the values themselves, all
+    /// as zig-zag encoded LEB128 varints
+    Binary,
+}
+
+/// Whether the data is gzip compressed.
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(GZIP_MAGIC)
+}
+
+/// Guesses the format of uncompressed program data. Commas only count
+/// outside of `#` comments.
+pub fn detect(bytes: &[u8]) -> Format {
+    if bytes.starts_with(BINARY_MAGIC) {
+        return Format::Binary;
+    }
+    let separated = bytes.split(|&b| b == b'\n').any(|line| uncommented(line).contains(&b','));
+    match bytes.iter().cloned().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => Format::Json,
+        _ if separated => Format::Text,
+        Some(_) => Format::Lines,
+        None => Format::Text,
+    }
+}
+
+fn uncommented(line: &[u8]) -> &[u8] {
+    line.split(|&b| b == b'#').next().unwrap_or(line)
+}
+
+/// Loads a program in any of the supported formats, compressed or not.
+pub fn load(bytes: &[u8]) -> Result<Vec<MemoryValueType>> {
+    if is_compressed(bytes) {
+        let data = decompress(bytes)?;
+        load_as(&data, detect(&data), false)
+    } else {
+        load_as(bytes, detect(bytes), false)
+    }
+}
+
+pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Vec<MemoryValueType>> {
+    load(&std::fs::read(path)?)
+}
+
+/// Loads a program in the given format without guessing.
+pub fn load_as(bytes: &[u8], format: Format, compressed: bool) -> Result<Vec<MemoryValueType>> {
+    if compressed {
+        return load_as(&decompress(bytes)?, format, false);
+    }
+    match format {
+        Format::Text => Ok(string_to_program(std::str::from_utf8(bytes)?)?),
+        Format::Json => parse_json(std::str::from_utf8(bytes)?),
+        Format::Lines => parse_lines(std::str::from_utf8(bytes)?),
+        Format::Binary => decode_binary(bytes),
+    }
+}
+
+/// Serializes a program, `load_as` with the same arguments reads it back.
+pub fn write(program: &[MemoryValueType], format: Format, compressed: bool) -> Result<Vec<u8>> {
+    let data = match format {
+        Format::Text => format!("{}\n", join(program, ",")).into_bytes(),
+        Format::Json => format!("[{}]\n", join(program, ",")).into_bytes(),
+        Format::Lines => format!("{}\n", join(program, "\n")).into_bytes(),
+        Format::Binary => encode_binary(program),
+    };
+    if compressed {
+        compress(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+pub fn write_file<P: AsRef<Path>>(path: P, program: &[MemoryValueType], format: Format, compressed: bool) -> Result<()> {
+    Ok(std::fs::write(path, write(program, format, compressed)?)?)
+}
+
+pub(crate) fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+pub(crate) fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn join(program: &[MemoryValueType], separator: &str) -> String {
+    program.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(separator)
+}
+
+fn parse_json(s: &str) -> Result<Vec<MemoryValueType>> {
+    let trimmed = s.trim_end();
+    let start = s.len() - s.trim_start().len();
+    if trimmed.len() < start + 2 || !trimmed[start..].starts_with('[') || !trimmed.ends_with(']') {
+        return Err(LoadError::NotAnArray);
+    }
+    string_to_program(&trimmed[start + 1..trimmed.len() - 1])
+        .map_err(|err| relocate(err, 0, start + 1).into())
+}
+
+/// Every line holds one value, blank lines and `#` comments are skipped.
+fn parse_lines(s: &str) -> Result<Vec<MemoryValueType>> {
+    let mut program = Vec::new();
+    let mut offset = 0;
+    for line in s.split('\n') {
+        if let Some(comma) = uncommented(line.as_bytes()).iter().position(|&b| b == b',') {
+            return Err(LoadError::Comma(offset + comma));
+        }
+        let values = string_to_program(line).map_err(|err| relocate(err, program.len(), offset))?;
+        program.extend(values);
+        offset += line.len() + 1;
+    }
+    Ok(program)
+}
+
+/// Moves the position of an error found in a part of the input.
+fn relocate(err: ParseError, first_index: usize, first_offset: usize) -> ParseError {
+    match err {
+        ParseError::InvalidValue { token, index, offset } => {
+            ParseError::InvalidValue { token, index: index + first_index, offset: offset + first_offset }
+        }
+        ParseError::OutOfRange { token, index, offset } => {
+            ParseError::OutOfRange { token, index: index + first_index, offset: offset + first_offset }
+        }
+        ParseError::MissingValue { index, offset } => {
+            ParseError::MissingValue { index: index + first_index, offset: offset + first_offset }
+        }
+        ParseError::MissingSeparator { token, index, offset } => {
+            ParseError::MissingSeparator { token, index: index + first_index, offset: offset + first_offset }
+        }
+    }
+}
+
+fn encode_binary(program: &[MemoryValueType]) -> Vec<u8> {
+    let mut data = BINARY_MAGIC.to_vec();
+    write_varint(&mut data, program.len() as u64);
+    for &value in program {
+        write_varint(&mut data, ((value << 1) ^ (value >> 63)) as u64);
+    }
+    data
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<Vec<MemoryValueType>> {
+    if !bytes.starts_with(BINARY_MAGIC) {
+        return Err(LoadError::MissingHeader);
+    }
+    let mut pos = BINARY_MAGIC.len();
+    let count = read_varint(bytes, &mut pos)?;
+    // every value takes at least one byte, so this bounds the allocation
+    let mut program = Vec::with_capacity((count as usize).min(bytes.len() - pos));
+    for _ in 0..count {
+        let raw = read_varint(bytes, &mut pos)?;
+        program.push((raw >> 1) as MemoryValueType ^ -((raw & 1) as MemoryValueType));
+    }
+    if pos != bytes.len() {
+        return Err(LoadError::TrailingData(pos));
+    }
+    Ok(program)
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let start = *pos;
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*pos).ok_or(LoadError::Truncated(start))?;
+        *pos += 1;
+        if shift == 63 && byte > 1 || shift > 63 {
+            return Err(LoadError::Overlong(start));
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &[MemoryValueType] = &[109, -1, 204, 1, 99, 0, 9223372036854775807, -9223372036854775808];
+
+    #[test]
+    fn test_round_trip() {
+        for &format in &[Format::Text, Format::Json, Format::Lines, Format::Binary] {
+            for &compressed in &[false, true] {
+                let data = write(PROGRAM, format, compressed).unwrap();
+                assert_eq!(is_compressed(&data), compressed);
+                assert_eq!(load(&data).unwrap(), PROGRAM, "{:?} (compressed: {})", format, compressed);
+                assert_eq!(load_as(&data, format, compressed).unwrap(), PROGRAM);
+            }
+        }
+        assert_eq!(write(&[1, -1, 64], Format::Binary, false).unwrap(), b"ICB1\x03\x02\x01\x80\x01");
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"1,0,0,3,99"), Format::Text);
+        assert_eq!(detect(b"  [1, 0, 0, 3, 99]"), Format::Json);
+        assert_eq!(detect(b"# one per line\n1\n0\n99\n"), Format::Lines);
+        assert_eq!(detect(b"# one, two and three\n1\n2 # not 2, 3\n3\n"), Format::Lines);
+        assert_eq!(detect(b"# a comment\n1,\n2\n"), Format::Text);
+        assert_eq!(detect(b"ICB1\x00"), Format::Binary);
+        assert_eq!(load(b" [ 1, 2 ,3 ]\n").unwrap(), vec![1, 2, 3]);
+        assert_eq!(load(b"1\n\n-2 # comment\n3").unwrap(), vec![1, -2, 3]);
+        assert_eq!(load(b"").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_errors() {
+        match load(b"[1, x]") {
+            Err(LoadError::Parse(err)) => {
+                assert_eq!(err, ParseError::InvalidValue { token: "x".to_string(), index: 1, offset: 4 });
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match load(b"1\n2\nx\n") {
+            Err(LoadError::Parse(err)) => {
+                assert_eq!(err, ParseError::InvalidValue { token: "x".to_string(), index: 2, offset: 4 });
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            load_as(b"1\n2,3\n", Format::Lines, false).unwrap_err().to_string(),
+            "Unexpected `,` at byte 3 of a program with one value per line"
+        );
+        assert!(match load_as(b"1,2", Format::Json, false) { Err(LoadError::NotAnArray) => true, _ => false });
+        assert!(match load(b"ICB1\x02\x02") { Err(LoadError::Truncated(6)) => true, _ => false });
+        assert!(match load(b"ICB1\x01\x02\x02") { Err(LoadError::TrailingData(6)) => true, _ => false });
+        let overlong = b"ICB1\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02";
+        assert!(match load(overlong) { Err(LoadError::Overlong(5)) => true, _ => false });
+        assert!(match load(b"\x1f\x8b\x08garbage") { Err(LoadError::Io(_)) => true, _ => false });
+    }
+}
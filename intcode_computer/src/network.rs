@@ -0,0 +1,232 @@
+use crate::error::NetworkError;
+use crate::memory::MemoryValueType;
+use crate::virtual_machine::{VirtualMachine, VMState};
+use std::collections::VecDeque;
+
+pub type Result<T> = std::result::Result<T, NetworkError>;
+
+/// Read by a machine whose input queue is empty
+pub const NO_PACKET: MemoryValueType = -1;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub destination: MemoryValueType,
+    pub x: MemoryValueType,
+    pub y: MemoryValueType,
+}
+
+struct Interface {
+    vm: VirtualMachine,
+    queue: VecDeque<MemoryValueType>,
+    /// Outputs of a packet that is not complete yet
+    outgoing: Vec<MemoryValueType>,
+    /// Value of the traffic counter when the machine last read `NO_PACKET`
+    polled: Option<u64>,
+    halted: bool,
+}
+
+/// Machines exchanging packets, every machine is booted with its address as
+/// first input.
+///
+/// A machine sends a packet by outputting its destination, `x` and `y`, and
+/// receives one as two inputs `x` and `y`. Packets to the monitor address are
+/// not delivered but handed to the caller. The machines run in rounds, in
+/// every round each machine runs until it waits for input on an empty queue
+/// for the second time, the first time it reads `NO_PACKET`.
+///
+/// The network is idle once no packets are queued and every running machine
+/// has read `NO_PACKET` since anything was last sent or received.
+///
+/// ```
+/// use intcode_computer::network::{Network, Packet};
+///
+/// // every machine sends its address to the monitor
+/// let program = [3,9, 104,255, 4,9, 4,9, 99, 0];
+/// let mut network = Network::new(&program, 2, 255).unwrap();
+/// assert_eq!(network.run_until_idle().unwrap(), vec![
+///     Packet { destination: 255, x: 0, y: 0 },
+///     Packet { destination: 255, x: 1, y: 1 },
+/// ]);
+/// ```
+pub struct Network {
+    interfaces: Vec<Interface>,
+    monitor: MemoryValueType,
+    /// Number of values sent or received so far
+    traffic: u64,
+}
+
+impl Network {
+    /// Boots `size` machines running `program` with the addresses
+    /// `0..size`.
+    pub fn new(program: &[MemoryValueType], size: usize, monitor: MemoryValueType) -> Result<Network> {
+        let mut interfaces = Vec::with_capacity(size);
+        for machine in 0..size {
+            let vm = VirtualMachine::new(program).map_err(|error| NetworkError::Machine { machine, error })?;
+            let queue = vec![machine as MemoryValueType].into_iter().collect();
+            interfaces.push(Interface { vm, queue, outgoing: Vec::new(), polled: None, halted: false });
+        }
+        Ok(Network { interfaces, monitor, traffic: 0 })
+    }
+
+    /// Delivers a packet from outside the network.
+    pub fn send(&mut self, packet: Packet) -> Result<()> {
+        let interface = self.interface(packet.destination)?;
+        interface.queue.extend(&[packet.x, packet.y]);
+        self.traffic += 1;
+        Ok(())
+    }
+
+    fn interface(&mut self, address: MemoryValueType) -> Result<&mut Interface> {
+        if address < 0 {
+            return Err(NetworkError::UnknownAddress(address));
+        }
+        self.interfaces.get_mut(address as usize).ok_or(NetworkError::UnknownAddress(address))
+    }
+
+    /// Whether no packets are queued or half sent and every running machine
+    /// has read `NO_PACKET` since the last traffic.
+    pub fn is_idle(&self) -> bool {
+        self.interfaces.iter().all(|interface| {
+            interface.halted
+                || (interface.queue.is_empty() && interface.outgoing.is_empty() && interface.polled == Some(self.traffic))
+        })
+    }
+
+    /// Runs every machine once and returns the packets sent to the monitor.
+    pub fn round(&mut self) -> Result<Vec<Packet>> {
+        let mut monitored = Vec::new();
+        for machine in 0..self.interfaces.len() {
+            self.advance(machine, &mut monitored)?;
+        }
+        Ok(monitored)
+    }
+
+    /// Runs at least one round and then more until the network is idle,
+    /// returns the packets sent to the monitor in the meantime.
+    pub fn run_until_idle(&mut self) -> Result<Vec<Packet>> {
+        let mut monitored = self.round()?;
+        while !self.is_idle() {
+            monitored.extend(self.round()?);
+        }
+        Ok(monitored)
+    }
+
+    fn advance(&mut self, machine: usize, monitored: &mut Vec<Packet>) -> Result<()> {
+        let error = |error| NetworkError::Machine { machine, error };
+        let mut polled = false;
+        loop {
+            let interface = &mut self.interfaces[machine];
+            if interface.halted {
+                return Ok(());
+            }
+            match interface.vm.run().map_err(error)? {
+                VMState::Halted => interface.halted = true,
+                _ if interface.vm.output_register().is_some() => {
+                    self.traffic += 1;
+                    interface.outgoing.push(interface.vm.output().map_err(error)?);
+                    if let [destination, x, y] = interface.outgoing[..] {
+                        interface.outgoing.clear();
+                        let packet = Packet { destination, x, y };
+                        if destination == self.monitor {
+                            monitored.push(packet);
+                        } else {
+                            self.interface(destination)?.queue.extend(&[x, y]);
+                        }
+                    }
+                }
+                _ => {
+                    let input = match interface.queue.pop_front() {
+                        Some(value) => {
+                            self.traffic += 1;
+                            value
+                        }
+                        None if !polled => {
+                            polled = true;
+                            interface.polled = Some(self.traffic);
+                            NO_PACKET
+                        }
+                        None => return Ok(()),
+                    };
+                    interface.vm.input(input).map_err(error)?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    // forwards every packet to the next machine with `y` incremented, the
+    // last machine sends to the monitor and machine 0 starts with a packet
+    const RELAY: &str = "
+                in address
+                jnz address, #receive
+                out #1
+                out #7
+                out #1
+        receive: in x
+                eq x, #-1, last
+                jnz last, #receive
+                in y
+                add y, #1, y
+                add address, #1, next
+                eq next, #3, last
+                jz last, #send
+                add #255, #0, next
+        send:   out next
+                out x
+                out y
+                jz #0, #receive
+        address: .word 0
+        x:      .word 0
+        y:      .word 0
+        next:   .word 0
+        last:   .word 0
+    ";
+
+    #[test]
+    fn test_relay() {
+        let program = assemble("relay", RELAY).unwrap().code;
+        let mut network = Network::new(&program, 3, 255).unwrap();
+        assert_eq!(network.run_until_idle().unwrap(), vec![Packet { destination: 255, x: 7, y: 3 }]);
+        assert!(network.is_idle());
+        assert!(network.round().unwrap().is_empty());
+        assert!(network.is_idle());
+
+        network.send(Packet { destination: 0, x: 5, y: 0 }).unwrap();
+        assert!(!network.is_idle());
+        assert_eq!(network.run_until_idle().unwrap(), vec![Packet { destination: 255, x: 5, y: 3 }]);
+        network.send(Packet { destination: 3, x: 0, y: 0 }).expect_err("there is no machine 3");
+    }
+
+    #[test]
+    fn test_idle() {
+        // only sends to the monitor after reading `NO_PACKET` three times
+        let program = assemble("patient", "
+                    in address
+            poll:   in x
+                    add polls, #1, polls
+                    lt polls, #3, wait
+                    jnz wait, #poll
+                    out #255
+                    out address
+                    out polls
+                    halt
+            address: .word 0
+            x:      .word 0
+            polls:  .word 0
+            wait:   .word 0
+        ").unwrap().code;
+        let mut network = Network::new(&program, 2, 255).unwrap();
+        // machine 1 reading its address is traffic after machine 0 polled
+        assert!(network.run_until_idle().unwrap().is_empty());
+        assert!(network.is_idle());
+        assert_eq!(network.run_until_idle().unwrap(), vec![
+            Packet { destination: 255, x: 0, y: 3 },
+            Packet { destination: 255, x: 1, y: 3 },
+        ]);
+    }
+}
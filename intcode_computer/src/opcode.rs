@@ -1,7 +1,10 @@
 use num_enum::TryFromPrimitive;
 use std::fmt;
+use std::convert::TryFrom;
+use crate::error::Result;
+use crate::memory::MemoryValueType;
 
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
 #[repr(u8)]
 pub enum Opcode {
      Add =  1,
@@ -16,7 +19,7 @@ pub enum Opcode {
     Halt = 99
 }
 
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
 #[repr(u8)]
 pub enum ParameterMode {
     Position = 0,
@@ -24,6 +27,42 @@ pub enum ParameterMode {
     Relative = 2   
 }
 
+impl Opcode {
+    /// Number of parameters that follow the instruction word.
+    pub fn parameter_count(&self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::LT | Opcode::EQ => 3,
+            Opcode::JNZ | Opcode::JZ => 2,
+            Opcode::In | Opcode::Out | Opcode::RBO => 1,
+            Opcode::Halt => 0,
+        }
+    }
+
+    /// Index of the parameter the instruction writes to, if any.
+    pub fn destination(&self) -> Option<usize> {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::LT | Opcode::EQ => Some(2),
+            Opcode::In => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// Extracts the opcode from an instruction word.
+pub fn decode_opcode(word: MemoryValueType) -> Result<Opcode> {
+    Ok(Opcode::try_from((word % 100) as u8)?)
+}
+
+/// Extracts the modes of all three possible parameters from an instruction
+/// word. Like the machine itself, this fails on any invalid mode digit, even
+/// for parameters the opcode does not use.
+pub fn decode_parameter_modes(word: MemoryValueType) -> Result<[ParameterMode; 3]> {
+    let fst = ParameterMode::try_from(((word /   100) % 10) as u8)?;
+    let snd = ParameterMode::try_from(((word /  1000) % 10) as u8)?;
+    let thd = ParameterMode::try_from(((word / 10000) % 10) as u8)?;
+    Ok([fst, snd, thd])
+}
+
 impl fmt::Display for Opcode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -0,0 +1,113 @@
+use crate::error::{Result, VMError};
+use crate::memory::MemoryValueType;
+use crate::virtual_machine::{VirtualMachine, VMState};
+use std::iter::FromFn;
+
+/// The outputs of a machine as an iterator, running the machine on demand
+/// and answering its `In` instructions from an input iterator.
+///
+/// Iteration ends when the machine halts. An error ends it as well after it
+/// has been returned, this includes running out of inputs, which is
+/// `VMError::InputClosed`.
+///
+/// ```
+/// use intcode_computer::outputs::Outputs;
+/// use intcode_computer::virtual_machine::VirtualMachine;
+///
+/// // doubles its input unless it is zero
+/// let vm = VirtualMachine::new(&[3,12, 1006,12,11, 1002,12,2,12, 4,12, 99, 0]).unwrap();
+/// let doubled: Vec<i64> = Outputs::new(vm, vec![21]).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(doubled, vec![42]);
+/// ```
+pub struct Outputs<I> {
+    vm: VirtualMachine,
+    inputs: I,
+    done: bool,
+}
+
+impl<I: Iterator<Item = MemoryValueType>> Outputs<I> {
+    pub fn new<T: IntoIterator<IntoIter = I, Item = MemoryValueType>>(vm: VirtualMachine, inputs: T) -> Outputs<I> {
+        Outputs { vm, inputs: inputs.into_iter(), done: false }
+    }
+
+    pub fn vm(&self) -> &VirtualMachine {
+        &self.vm
+    }
+
+    pub fn into_vm(self) -> VirtualMachine {
+        self.vm
+    }
+
+    fn advance(&mut self) -> Result<Option<MemoryValueType>> {
+        loop {
+            match self.vm.run()? {
+                VMState::Halted => return Ok(None),
+                _ if self.vm.output_register().is_some() => return self.vm.output().map(Some),
+                _ => {
+                    let input = self.inputs.next().ok_or(VMError::InputClosed)?;
+                    self.vm.input(input)?;
+                }
+            }
+        }
+    }
+}
+
+impl<F: FnMut() -> Option<MemoryValueType>> Outputs<FromFn<F>> {
+    /// Asks `input` for a value whenever the machine reads one.
+    pub fn from_fn(vm: VirtualMachine, input: F) -> Outputs<FromFn<F>> {
+        Outputs::new(vm, std::iter::from_fn(input))
+    }
+}
+
+impl<I: Iterator<Item = MemoryValueType>> Iterator for Outputs<I> {
+    type Item = Result<MemoryValueType>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.advance() {
+            Ok(Some(value)) => Some(Ok(value)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::ECHO_FOREVER;
+
+    // day 09 example that outputs a copy of itself
+    const QUINE: &[MemoryValueType] = &[109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+
+    #[test]
+    fn test_outputs() {
+        let outputs: Vec<MemoryValueType> = Outputs::new(VirtualMachine::new(QUINE).unwrap(), None)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(outputs, QUINE);
+
+        // outputs the running sum of its inputs
+        let program = [3,11, 1,11,12,12, 4,12, 1105,1,0, 0,0];
+        let mut next = 0;
+        let sums: Vec<MemoryValueType> = Outputs::from_fn(VirtualMachine::new(&program).unwrap(), || {
+            next += 1;
+            Some(next)
+        }).take(4).collect::<Result<_>>().unwrap();
+        assert_eq!(sums, vec![1, 3, 6, 10]);
+
+        let mut outputs = Outputs::new(VirtualMachine::new(ECHO_FOREVER).unwrap(), vec![5]);
+        assert_eq!(outputs.next().unwrap().unwrap(), 5);
+        outputs.next().unwrap().expect_err("the inputs ran out");
+        assert!(outputs.next().is_none());
+        assert_eq!(outputs.vm().pc(), 0);
+    }
+}
@@ -0,0 +1,352 @@
+use crate::error::PipelineError;
+use crate::memory::MemoryValueType;
+use crate::virtual_machine::{VirtualMachine, VMState};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+pub type Result<T> = std::result::Result<T, PipelineError>;
+
+struct Node {
+    vm: VirtualMachine,
+    /// Inputs consumed before anything is read from the queue
+    initial: VecDeque<MemoryValueType>,
+    targets: Vec<usize>,
+}
+
+/// What the thread of a machine is waiting for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Wait {
+    Nothing,
+    Input,
+    /// Room in the queue of the given machine
+    Output(usize),
+}
+
+/// The queues between the machines and the state of their threads.
+struct Links {
+    capacity: usize,
+    queues: Vec<VecDeque<MemoryValueType>>,
+    /// Machines feeding each machine
+    sources: Vec<Vec<usize>>,
+    done: Vec<bool>,
+    waits: Vec<Wait>,
+    deadlock: Option<Vec<usize>>,
+}
+
+impl Links {
+    /// Whether a machine can only continue after another one does.
+    fn is_stuck(&self, machine: usize) -> bool {
+        match self.waits[machine] {
+            Wait::Nothing => false,
+            Wait::Input => {
+                self.queues[machine].is_empty()
+                    && self.sources[machine].iter().any(|&source| !self.done[source])
+            }
+            Wait::Output(target) => !self.done[target] && self.queues[target].len() >= self.capacity,
+        }
+    }
+
+    /// The remaining machines if all of them are stuck.
+    fn stuck(&self) -> Option<Vec<usize>> {
+        let remaining: Vec<usize> = (0..self.done.len()).filter(|&machine| !self.done[machine]).collect();
+        if remaining.iter().all(|&machine| self.is_stuck(machine)) {
+            Some(remaining)
+        } else {
+            None
+        }
+    }
+}
+
+struct Shared {
+    links: Mutex<Links>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Links> {
+        // a machine never panics while holding the lock
+        self.links.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes the next input of a machine, `None` once no machine can feed it
+    /// anymore.
+    fn receive(&self, machine: usize) -> Result<Option<MemoryValueType>> {
+        let mut links = self.lock();
+        loop {
+            if let Some(value) = links.queues[machine].pop_front() {
+                self.changed.notify_all();
+                return Ok(Some(value));
+            }
+            if links.sources[machine].iter().all(|&source| links.done[source]) {
+                return Ok(None);
+            }
+            links = self.wait(links, machine, Wait::Input)?;
+        }
+    }
+
+    /// Queues a value for `target`, the value is dropped if `target` is done.
+    fn send(&self, machine: usize, target: usize, value: MemoryValueType) -> Result<()> {
+        let mut links = self.lock();
+        loop {
+            if links.done[target] {
+                return Ok(());
+            }
+            if links.queues[target].len() < links.capacity {
+                links.queues[target].push_back(value);
+                self.changed.notify_all();
+                return Ok(());
+            }
+            links = self.wait(links, machine, Wait::Output(target))?;
+        }
+    }
+
+    /// Waits for another machine to change something. If all remaining
+    /// machines are waiting for each other, none of them ever will.
+    fn wait<'a>(&self, mut links: MutexGuard<'a, Links>, machine: usize, wait: Wait) -> Result<MutexGuard<'a, Links>> {
+        if links.deadlock.is_none() {
+            links.waits[machine] = wait;
+            match links.stuck() {
+                Some(machines) => {
+                    links.deadlock = Some(machines);
+                    self.changed.notify_all();
+                }
+                None => links = self.changed.wait(links).unwrap_or_else(|poisoned| poisoned.into_inner()),
+            }
+            links.waits[machine] = Wait::Nothing;
+        }
+        match &links.deadlock {
+            Some(machines) => Err(PipelineError::Deadlock(machines.clone())),
+            None => Ok(links),
+        }
+    }
+
+    fn finish(&self, machine: usize) {
+        self.lock().done[machine] = true;
+        self.changed.notify_all();
+    }
+}
+
+/// Marks a machine as done when its thread ends, even if it panics.
+struct Finish<'a> {
+    shared: &'a Shared,
+    machine: usize,
+}
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        self.shared.finish(self.machine);
+    }
+}
+
+/// Machines on their own threads, connected by bounded queues.
+///
+/// Every output of a machine is sent to all machines it is connected to,
+/// inputs from several machines are merged in the order they arrive. A
+/// machine stops once it halts or all machines feeding it are gone, and
+/// outputs to machines that are gone are dropped. So when one machine halts,
+/// the others shut down as soon as they need input from it.
+///
+/// If the remaining machines all wait for each other, either for input or
+/// for room in a full queue, the run fails with `Deadlock` instead of
+/// hanging.
+///
+/// ```
+/// use intcode_computer::pipeline::Pipeline;
+///
+/// // adds one to every input
+/// let program = [3,11, 1001,11,1,11, 4,11, 1105,1,0, 0];
+/// let mut pipeline = Pipeline::new(1);
+/// let first = pipeline.add(&program, &[1, 2]).unwrap();
+/// let second = pipeline.add(&program, &[]).unwrap();
+/// pipeline.connect(first, second).unwrap();
+/// assert_eq!(pipeline.run().unwrap(), vec![Some(3), Some(4)]);
+/// ```
+pub struct Pipeline {
+    capacity: usize,
+    nodes: Vec<Node>,
+}
+
+impl Pipeline {
+    /// `capacity` is the number of values a queue holds before the sending
+    /// machine blocks, it is at least one.
+    pub fn new(capacity: usize) -> Pipeline {
+        Pipeline { capacity: capacity.max(1), nodes: Vec::new() }
+    }
+
+    /// Adds a machine that first reads the given inputs and returns its id.
+    pub fn add(&mut self, program: &[MemoryValueType], inputs: &[MemoryValueType]) -> Result<usize> {
+        let machine = self.nodes.len();
+        let vm = VirtualMachine::new(program).map_err(|error| PipelineError::Machine { machine, error })?;
+        self.nodes.push(Node { vm, initial: inputs.iter().cloned().collect(), targets: Vec::new() });
+        Ok(machine)
+    }
+
+    /// Queues more initial inputs for a machine.
+    pub fn feed(&mut self, machine: usize, inputs: &[MemoryValueType]) -> Result<()> {
+        self.node(machine)?.initial.extend(inputs);
+        Ok(())
+    }
+
+    /// Sends the outputs of `from` to `to`.
+    pub fn connect(&mut self, from: usize, to: usize) -> Result<()> {
+        self.node(to)?;
+        self.node(from)?.targets.push(to);
+        Ok(())
+    }
+
+    fn node(&mut self, machine: usize) -> Result<&mut Node> {
+        self.nodes.get_mut(machine).ok_or(PipelineError::UnknownMachine(machine))
+    }
+
+    /// Runs all machines to the end and returns the last output of each.
+    /// Fails with the error of the first failing machine.
+    pub fn run(self) -> Result<Vec<Option<MemoryValueType>>> {
+        let count = self.nodes.len();
+        let mut sources = vec![Vec::new(); count];
+        for (from, node) in self.nodes.iter().enumerate() {
+            for &to in &node.targets {
+                sources[to].push(from);
+            }
+        }
+        let shared = Arc::new(Shared {
+            links: Mutex::new(Links {
+                capacity: self.capacity,
+                queues: vec![VecDeque::new(); count],
+                sources,
+                done: vec![false; count],
+                waits: vec![Wait::Nothing; count],
+                deadlock: None,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let mut handles = Vec::with_capacity(count);
+        for (machine, node) in self.nodes.into_iter().enumerate() {
+            let shared = Arc::clone(&shared);
+            handles.push(thread::spawn(move || {
+                let _finish = Finish { shared: &shared, machine };
+                drive(machine, node, &shared)
+            }));
+        }
+
+        let mut results = Vec::with_capacity(count);
+        let mut failure = None;
+        for (machine, handle) in handles.into_iter().enumerate() {
+            let result = match handle.join() {
+                Ok(Ok(last)) => Some(last),
+                Ok(Err(error)) => {
+                    failure = failure.or(Some(error));
+                    None
+                }
+                Err(_) => {
+                    failure = failure.or(Some(PipelineError::Panicked(machine)));
+                    None
+                }
+            };
+            results.push(result.and_then(|last| last));
+        }
+        match failure {
+            Some(failure) => Err(failure),
+            None => Ok(results),
+        }
+    }
+}
+
+/// Runs one machine and returns its last output.
+fn drive(machine: usize, node: Node, shared: &Shared) -> Result<Option<MemoryValueType>> {
+    let error = |error| PipelineError::Machine { machine, error };
+    let Node { mut vm, mut initial, targets } = node;
+    let mut last = None;
+    loop {
+        match vm.run().map_err(error)? {
+            VMState::Halted => return Ok(last),
+            _ if vm.output_register().is_some() => {
+                let value = vm.output().map_err(error)?;
+                last = Some(value);
+                for &target in &targets {
+                    shared.send(machine, target, value)?;
+                }
+            }
+            _ => {
+                let value = match initial.pop_front() {
+                    Some(value) => value,
+                    None => match shared.receive(machine)? {
+                        Some(value) => value,
+                        None => return Ok(last),
+                    },
+                };
+                vm.input(value).map_err(error)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{ECHO, ECHO_FOREVER, FEEDBACK};
+
+    #[test]
+    fn test_feedback_loop() {
+        let mut pipeline = Pipeline::new(1);
+        let amplifiers: Vec<usize> = [9, 8, 7, 6, 5].iter().map(|&phase| pipeline.add(FEEDBACK, &[phase]).unwrap()).collect();
+        pipeline.feed(amplifiers[0], &[0]).unwrap();
+        for (i, &from) in amplifiers.iter().enumerate() {
+            pipeline.connect(from, amplifiers[(i + 1) % amplifiers.len()]).unwrap();
+        }
+        assert_eq!(pipeline.run().unwrap()[4], Some(139629729));
+    }
+
+    #[test]
+    fn test_fan_in() {
+        let add = [3,0, 3,1, 1,0,1,0, 4,0, 99];
+        let mut pipeline = Pipeline::new(1);
+        let left = pipeline.add(ECHO, &[20]).unwrap();
+        let right = pipeline.add(ECHO, &[22]).unwrap();
+        let sum = pipeline.add(&add, &[]).unwrap();
+        // keeps asking for input after the sum halted
+        let last = pipeline.add(ECHO_FOREVER, &[]).unwrap();
+        pipeline.connect(left, sum).unwrap();
+        pipeline.connect(right, sum).unwrap();
+        pipeline.connect(sum, last).unwrap();
+        assert_eq!(pipeline.run().unwrap(), vec![Some(20), Some(22), Some(42), Some(42)]);
+        Pipeline::new(1).connect(0, 0).expect_err("there is no machine 0");
+    }
+
+    #[test]
+    fn test_deadlock() {
+        // both wait for the other before they output anything
+        let mut pipeline = Pipeline::new(1);
+        pipeline.add(ECHO, &[]).unwrap();
+        pipeline.add(ECHO, &[]).unwrap();
+        pipeline.connect(0, 1).unwrap();
+        pipeline.connect(1, 0).unwrap();
+        assert_eq!(pipeline.run().unwrap_err().to_string(), "Deadlock, machines [0, 1] wait for each other");
+
+        // both output twice before they read, which only fits if a queue
+        // holds two values
+        let chatty = [104,1, 104,2, 3,7, 99, 0];
+        let mut pipeline = Pipeline::new(1);
+        pipeline.add(&chatty, &[]).unwrap();
+        pipeline.add(&chatty, &[]).unwrap();
+        pipeline.connect(0, 1).unwrap();
+        pipeline.connect(1, 0).unwrap();
+        assert_eq!(pipeline.run().unwrap_err().to_string(), "Deadlock, machines [0, 1] wait for each other");
+
+        let mut pipeline = Pipeline::new(2);
+        pipeline.add(&chatty, &[]).unwrap();
+        pipeline.add(&chatty, &[]).unwrap();
+        pipeline.connect(0, 1).unwrap();
+        pipeline.connect(1, 0).unwrap();
+        assert_eq!(pipeline.run().unwrap(), vec![Some(2), Some(2)]);
+
+        // the first machine shuts down once the second fails
+        let mut pipeline = Pipeline::new(1);
+        pipeline.add(ECHO_FOREVER, &[1]).unwrap();
+        pipeline.add(&[3,0, 42], &[]).unwrap();
+        pipeline.connect(0, 1).unwrap();
+        pipeline.connect(1, 0).unwrap();
+        assert_eq!(pipeline.run().unwrap_err().to_string(), "Machine 1: Unknown opcode");
+    }
+}
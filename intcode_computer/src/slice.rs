@@ -0,0 +1,237 @@
+use crate::error::SliceError;
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, decode_opcode};
+use crate::sourcemap::{self, DebugInfo};
+use crate::virtual_machine::{Accesses, VirtualMachine, VMState};
+use std::collections::{BTreeSet, HashSet};
+
+pub type Result<T> = std::result::Result<T, SliceError>;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+enum Location {
+    Cell(usize),
+    RelativeBase,
+}
+
+struct TraceStep {
+    pc: usize,
+    accesses: Accesses,
+    /// Index of the input consumed by this step
+    input: Option<usize>,
+}
+
+/// Everything that contributed to one output value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Slice {
+    /// Addresses of the instructions in the slice
+    pub instructions: BTreeSet<usize>,
+    /// Data cells read by the slice and operand words it overwrote
+    pub cells: BTreeSet<usize>,
+    /// Indices of the consumed inputs the value depends on
+    pub inputs: BTreeSet<usize>,
+    /// Number of executed steps in the slice
+    pub steps: usize,
+}
+
+impl Slice {
+    /// Source lines of the instructions in the slice.
+    pub fn locations<'a>(&self, info: &'a DebugInfo) -> BTreeSet<&'a sourcemap::Location> {
+        self.instructions.iter().filter_map(|&address| info.location(address)).collect()
+    }
+}
+
+/// Record of a complete program run, used to compute dynamic slices.
+///
+/// Slices only follow data dependences (including writes to operands and the
+/// relative base). Conditional jumps that decided whether an instruction ran
+/// at all are not part of a slice.
+pub struct Trace {
+    steps: Vec<TraceStep>,
+    // (step index, output address) of every emitted value
+    outputs: Vec<(usize, usize)>,
+    values: Vec<MemoryValueType>,
+}
+
+impl Trace {
+    /// Runs the program with the given inputs until it halts or needs more
+    /// input and records every executed instruction. Fails with `StepLimit`
+    /// if that takes more than `max_steps` instructions.
+    pub fn record(program: &[MemoryValueType], inputs: &[MemoryValueType], max_steps: usize) -> Result<Trace> {
+        let mut vm = VirtualMachine::new(program)?;
+        let mut inputs = inputs.iter().cloned();
+        let mut consumed = 0;
+        let mut trace = Trace { steps: Vec::new(), outputs: Vec::new(), values: Vec::new() };
+
+        loop {
+            if trace.steps.len() == max_steps {
+                return Err(SliceError::StepLimit(max_steps));
+            }
+            let opcode = decode_opcode(vm[vm.pc()])?;
+            let mut input = None;
+            if opcode == Opcode::In {
+                match inputs.next() {
+                    Some(val) => vm.input(val)?,
+                    None => break,
+                }
+                input = Some(consumed);
+                consumed += 1;
+            }
+
+            let step = TraceStep { pc: vm.pc(), accesses: vm.next_accesses()?, input };
+            vm.resume();
+            vm.step()?;
+            if opcode == Opcode::Out {
+                trace.outputs.push((trace.steps.len(), step.pc));
+                trace.values.push(vm.output()?);
+            }
+            trace.steps.push(step);
+            if vm.state() == VMState::Halted {
+                break;
+            }
+        }
+        Ok(trace)
+    }
+
+    /// Source lines in the order they were executed. A line that runs several
+    /// instructions in a row is listed once.
+    pub fn lines<'a>(&self, info: &'a DebugInfo) -> Vec<&'a sourcemap::Location> {
+        let mut lines: Vec<&sourcemap::Location> = Vec::new();
+        for location in self.steps.iter().filter_map(|step| info.location(step.pc)) {
+            if lines.last() != Some(&location) {
+                lines.push(location);
+            }
+        }
+        lines
+    }
+
+    /// Values emitted during the run.
+    pub fn outputs(&self) -> &[MemoryValueType] {
+        &self.values
+    }
+
+    /// Slice of the n-th emitted value.
+    pub fn slice_output(&self, index: usize) -> Option<Slice> {
+        self.outputs.get(index).map(|&(step, _)| self.slice_step(step))
+    }
+
+    /// Slice of the last value emitted by the `Out` instruction at the given
+    /// address.
+    pub fn slice(&self, address: usize) -> Option<Slice> {
+        self.outputs.iter()
+            .rev()
+            .find(|(_, out)| *out == address)
+            .map(|&(step, _)| self.slice_step(step))
+    }
+
+    fn slice_step(&self, index: usize) -> Slice {
+        let mut slice = Slice::default();
+        let mut live = HashSet::new();
+        let mut operands = HashSet::new();
+
+        include(&mut slice, &mut live, &mut operands, &self.steps[index]);
+        for step in self.steps[..index].iter().rev() {
+            let mut defines = false;
+            if let Some(address) = step.accesses.write {
+                if live.remove(&Location::Cell(address)) {
+                    defines = true;
+                    if operands.contains(&address) {
+                        slice.cells.insert(address);
+                    }
+                }
+            }
+            if step.accesses.sets_relative_base && live.remove(&Location::RelativeBase) {
+                defines = true;
+            }
+            if defines {
+                include(&mut slice, &mut live, &mut operands, step);
+            }
+        }
+        slice
+    }
+}
+
+fn include(slice: &mut Slice, live: &mut HashSet<Location>, operands: &mut HashSet<usize>, step: &TraceStep) {
+    slice.instructions.insert(step.pc);
+    slice.steps += 1;
+    slice.inputs.extend(step.input);
+    slice.cells.extend(step.accesses.reads.iter().cloned());
+    live.extend(step.accesses.reads.iter().map(|a| Location::Cell(*a)));
+    live.extend(step.accesses.operands.iter().map(|a| Location::Cell(*a)));
+    operands.extend(step.accesses.operands.iter().cloned());
+    if step.accesses.uses_relative_base {
+        live.insert(Location::RelativeBase);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+
+    #[test]
+    fn test_slice() {
+        // a = in0 * 2; b = in1 + 5; out(a); out(b)
+        let program = vec![3,20,3,21,1002,20,2,22,1001,21,5,23,4,22,4,23,99];
+        let trace = Trace::record(&program, &[3, 4], 100).unwrap();
+        assert_eq!(trace.outputs(), &[6, 9]);
+
+        let slice = trace.slice(12).unwrap();
+        assert_eq!(slice.instructions.into_iter().collect::<Vec<_>>(), vec![0, 4, 12]);
+        assert_eq!(slice.cells.into_iter().collect::<Vec<_>>(), vec![20, 22]);
+        assert_eq!(slice.inputs.into_iter().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(slice.steps, 3);
+
+        let slice = trace.slice_output(1).unwrap();
+        assert_eq!(slice.instructions.into_iter().collect::<Vec<_>>(), vec![2, 8, 14]);
+        assert!(trace.slice(0).is_none());
+
+        match Trace::record(&[1105,1,0], &[], 100) {
+            Err(SliceError::StepLimit(100)) => (),
+            _ => panic!("an endless loop has to hit the step limit"),
+        }
+    }
+
+    #[test]
+    fn test_slice_through_relative_base_and_loops() {
+        // rb = 50; [rb+0] = in0; 3 times: [rb+0] += 1; out([rb+0])
+        // with the loop counter at 40
+        let program = vec![
+            109,50,203,0,1101,3,0,40,
+            22101,1,0,0,1001,40,-1,40,1005,40,8,
+            204,0,99
+        ];
+        let trace = Trace::record(&program, &[10], 100).unwrap();
+        assert_eq!(trace.outputs(), &[13]);
+
+        let slice = trace.slice(19).unwrap();
+        assert_eq!(slice.instructions.into_iter().collect::<Vec<_>>(), vec![0, 2, 8, 19]);
+        assert_eq!(slice.steps, 6);
+        assert_eq!(slice.inputs.len(), 1);
+    }
+
+    #[test]
+    fn test_source_lines() {
+        let source = "\
+fn main() {
+    let x = read();
+    let y = x + 1;
+    print(x * 2);
+}
+";
+        let image = compile(source).unwrap();
+        let info = image.debug_info();
+        let trace = Trace::record(&image.program, &[4], 1000).unwrap();
+        let lines: Vec<String> = trace.lines(&info).iter().map(|location| location.to_string()).collect();
+        assert_eq!(lines, vec!["main:1", "main:2", "main:3", "main:4"]);
+    }
+
+    #[test]
+    fn test_self_modification() {
+        // day 05 example: the input is written into an immediate operand
+        let program = vec![3,3,1107,-1,8,3,4,3,99];
+        let trace = Trace::record(&program, &[5], 100).unwrap();
+        let slice = trace.slice(6).unwrap();
+        assert_eq!(slice.instructions.into_iter().collect::<Vec<_>>(), vec![0, 2, 6]);
+        assert!(slice.cells.contains(&3));
+    }
+}
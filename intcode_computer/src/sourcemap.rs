@@ -0,0 +1,185 @@
+use crate::error::LinkError;
+use crate::linker::{Result, SymbolMap};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/// A line in a source file.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Maps ranges of addresses to the source line they were generated from.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SourceMap {
+    // start -> (end, location), the ranges do not overlap
+    ranges: BTreeMap<usize, (usize, Location)>,
+}
+
+impl SourceMap {
+    /// Adds a range. Adjacent ranges of the same line are merged.
+    pub fn insert(&mut self, range: Range<usize>, location: Location) {
+        if range.start >= range.end {
+            return;
+        }
+        if let Some((_, (end, previous))) = self.ranges.range_mut(..range.start).next_back() {
+            if *end == range.start && *previous == location {
+                *end = range.end;
+                return;
+            }
+        }
+        self.ranges.insert(range.start, (range.end, location));
+    }
+
+    pub fn location(&self, address: usize) -> Option<&Location> {
+        self.ranges.range(..=address)
+            .next_back()
+            .filter(|(_, (end, _))| address < *end)
+            .map(|(_, (_, location))| location)
+    }
+
+    /// Address ranges generated from a line, e.g. to place breakpoints.
+    pub fn addresses(&self, file: &str, line: usize) -> Vec<Range<usize>> {
+        self.ranges.iter()
+            .filter(|(_, (_, location))| location.file == file && location.line == line)
+            .map(|(&start, (end, _))| start..*end)
+            .collect()
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = (Range<usize>, &Location)> {
+        self.ranges.iter().map(|(&start, (end, location))| (start..*end, location))
+    }
+
+    /// Moves all ranges by the given number of words.
+    pub(crate) fn shifted(&self, by: usize) -> SourceMap {
+        let ranges = self.ranges.iter()
+            .map(|(&start, (end, location))| (start + by, (end + by, location.clone())))
+            .collect();
+        SourceMap { ranges }
+    }
+
+    pub(crate) fn extend(&mut self, other: SourceMap) {
+        for (start, (end, location)) in other.ranges {
+            self.insert(start..end, location);
+        }
+    }
+}
+
+/// Symbols and source lines of a linked program, which tools use to show
+/// addresses as `label+offset (file:line)`.
+///
+/// The text form is the symbol map (see `SymbolMap`) followed by one
+/// `source <start> <end> <line> <file>` line per address range.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DebugInfo {
+    pub symbols: SymbolMap,
+    pub source: SourceMap,
+}
+
+impl DebugInfo {
+    pub fn location(&self, address: usize) -> Option<&Location> {
+        self.source.location(address)
+    }
+
+    /// Human readable form of an address, falling back to the number.
+    pub fn describe(&self, address: usize) -> String {
+        let mut description = match self.symbols.symbolize(address) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+{}", name, offset),
+            None => address.to_string(),
+        };
+        if let Some(location) = self.location(address) {
+            description.push_str(&format!(" ({})", location));
+        }
+        description
+    }
+
+    pub fn parse(s: &str) -> Result<DebugInfo> {
+        let mut symbols = String::new();
+        let mut source = SourceMap::default();
+        for (i, line) in s.lines().enumerate() {
+            if !line.starts_with("source ") {
+                symbols.push_str(line);
+                symbols.push('\n');
+                continue;
+            }
+            let syntax = || LinkError::Syntax { line: i + 1, message: "invalid source map entry".to_string() };
+            let mut fields = line.splitn(5, ' ').skip(1);
+            let mut number = || fields.next().and_then(|field| field.parse::<usize>().ok()).ok_or_else(syntax);
+            let (start, end, line) = (number()?, number()?, number()?);
+            let file = fields.next().ok_or_else(syntax)?.to_string();
+            source.insert(start..end, Location { file, line });
+        }
+        Ok(DebugInfo { symbols: SymbolMap::parse(&symbols)?, source })
+    }
+}
+
+impl fmt::Display for DebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbols)?;
+        for (range, location) in self.source.ranges() {
+            writeln!(f, "source {} {} {} {}", range.start, range.end, location.line, location.file)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+
+    #[test]
+    fn test_compiled_program() {
+        let source = "\
+fn twice(x) {
+    return x * 2;
+}
+
+fn main() {
+    let x = read();
+    print(twice(x));
+}
+";
+        let image = compile(source).unwrap();
+        let info = image.debug_info();
+
+        let returns = info.source.addresses("main", 2);
+        assert_eq!(returns.len(), 1);
+        let description = info.describe(returns[0].start);
+        assert!(description.starts_with("main::f_twice"), "{}", description);
+        assert!(description.ends_with("(main:2)"), "{}", description);
+        assert!(!info.source.addresses("main", 7).is_empty());
+        assert!(info.source.addresses("main", 4).is_empty());
+
+        // the library is mapped to its assembler source
+        let divmod = info.symbols.address("divmod").unwrap();
+        assert_eq!(info.describe(divmod), "divmod (divmod:3)");
+        // the startup code has no source line
+        assert_eq!(info.describe(0), "0");
+
+        assert_eq!(DebugInfo::parse(&info.to_string()).unwrap(), info);
+    }
+
+    #[test]
+    fn test_merge() {
+        let location = |line| Location { file: "a".to_string(), line };
+        let mut map = SourceMap::default();
+        map.insert(0..2, location(1));
+        map.insert(2..4, location(1));
+        map.insert(4..6, location(2));
+        map.insert(8..9, location(1));
+        assert_eq!(map.addresses("a", 1), vec![0..4, 8..9]);
+        assert_eq!(map.location(5), Some(&location(2)));
+        assert_eq!(map.location(6), None);
+        assert_eq!(map.shifted(10).location(13), Some(&location(1)));
+    }
+}
@@ -0,0 +1,243 @@
+//! Reusable routines written for the `assembler`.
+//!
+//! All routines follow the stack convention of the `call`, `ret`, `push` and
+//! `pop` pseudo instructions: the relative base is the stack pointer and points
+//! to the next free word. Arguments are pushed in order, results replace the
+//! arguments and the caller pops them again. A routine only keeps temporary
+//! values above the stack pointer and addresses its data through the relative
+//! base, so it works wherever the stack is. No routine modifies its own code.
+//!
+//! Intcode can only jump to absolute addresses, so jump targets and return
+//! addresses are still relocated by the linker, as are the three words
+//! `memcpy` keeps in its module while it copies.
+//!
+//! Multiplication is a native instruction, division is emulated by `divmod`.
+//! Programs set up the stack with `rbo #stack` before the first call and link
+//! `objects()` after all of their own modules, as the stack grows from the end
+//! of the image.
+
+use crate::assembler::{Result, assemble};
+use crate::linker::Object;
+
+/// `divmod(dividend, divisor) -> (quotient, remainder)`, rounding towards
+/// zero like Rust does. Halts on a division by zero.
+pub const DIVMOD: &str = "
+.global divmod
+divmod:     jz [rb-2], #zero
+            ; [rb+0] and [rb+1] remember the signs, [rb+5] is the quotient
+            lt [rb-3], #0, [rb+0]
+            jz [rb+0], #a_positive
+            mul [rb-3], #-1, [rb-3]
+a_positive: lt [rb-2], #0, [rb+1]
+            jz [rb+1], #b_positive
+            mul [rb-2], #-1, [rb-2]
+b_positive: mov #0, [rb+5]
+            ; subtract the largest multiple d = m * divisor with d <= rest
+outer:      lt [rb-3], [rb-2], [rb+4]
+            jnz [rb+4], #signs
+            mov [rb-2], [rb+2]
+            mov #1, [rb+3]
+inner:      mul [rb+2], #-1, [rb+4]
+            add [rb-3], [rb+4], [rb+4]
+            lt [rb+4], [rb+2], [rb+4]
+            jnz [rb+4], #subtract
+            add [rb+2], [rb+2], [rb+2]
+            add [rb+3], [rb+3], [rb+3]
+            jmp #inner
+subtract:   mul [rb+2], #-1, [rb+4]
+            add [rb-3], [rb+4], [rb-3]
+            add [rb+5], [rb+3], [rb+5]
+            jmp #outer
+signs:      mov [rb-3], [rb-2]
+            mov [rb+5], [rb-3]
+            eq [rb+0], [rb+1], [rb+4]
+            jnz [rb+4], #remainder
+            mul [rb-3], #-1, [rb-3]
+remainder:  jz [rb+0], #done
+            mul [rb-2], #-1, [rb-2]
+done:       ret
+zero:       halt
+";
+
+/// `print_int(value)` outputs the value as ASCII decimal number.
+pub const PRINT_INT: &str = "
+.global print_int
+.extern divmod
+print_int:  lt [rb-2], #0, [rb+0]
+            jz [rb+0], #digits
+            out #45
+            mul [rb-2], #-1, [rb-2]
+            ; push the digits on top of a -1 sentinel, least significant first
+digits:     push #-1
+            push [rb-3]
+next:       push #10
+            call #divmod
+            add [rb-1], #48, [rb+0]
+            mov [rb-2], [rb-1]
+            mov [rb+0], [rb-2]
+            jnz [rb-1], #next
+            rbo #-1
+print:      rbo #-1
+            out [rb+0]
+            eq [rb-1], #-1, [rb+0]
+            jz [rb+0], #print
+            rbo #-1
+            ret
+";
+
+/// `read_int(_) -> value` reads an optionally negative ASCII decimal number.
+/// The first character that is not a digit ends the number and is dropped.
+pub const READ_INT: &str = "
+.global read_int
+            ; [rb+0] is the character, [rb+1] the sign
+read_int:   mov #0, [rb-2]
+            mov #1, [rb+1]
+            in [rb+0]
+            eq [rb+0], #45, [rb+2]
+            jz [rb+2], #digit
+            mov #-1, [rb+1]
+next:       in [rb+0]
+digit:      lt [rb+0], #48, [rb+2]
+            jnz [rb+2], #done
+            lt #57, [rb+0], [rb+2]
+            jnz [rb+2], #done
+            mul [rb-2], #10, [rb-2]
+            add [rb+0], #-48, [rb+2]
+            add [rb-2], [rb+2], [rb-2]
+            jmp #next
+done:       mul [rb-2], [rb+1], [rb-2]
+            ret
+";
+
+/// `memcpy(source, destination, count)` copies words front to back. Source
+/// and destination are offsets from the stack pointer before the arguments
+/// were pushed, like the `n` of a `[rb+n]` operand there.
+pub const MEMCPY: &str = "
+.global memcpy
+            ; make the offsets relative to the stack pointer of the routine
+memcpy:     add [rb-4], #-4, [rb-4]
+            add [rb-3], #-4, [rb-3]
+next:       jz [rb-2], #done
+            ; the stack can not be reached while the stack pointer is moved
+            ; to the words, so the steps back and the word are kept below
+            mul [rb-4], #-1, back
+            add [rb-3], back, step
+            mul [rb-3], #-1, back
+            rbo [rb-4]
+            mov [rb+0], word
+            rbo step
+            mov word, [rb+0]
+            rbo back
+            add [rb-4], #1, [rb-4]
+            add [rb-3], #1, [rb-3]
+            add [rb-2], #-1, [rb-2]
+            jmp #next
+done:       ret
+word:       .word 0
+step:       .word 0
+back:       .word 0
+";
+
+/// Empty module marking the start of the stack.
+pub const STACK: &str = "
+.global stack
+stack:
+";
+
+/// Names and sources of all modules in link order.
+pub const SOURCES: &[(&str, &str)] = &[
+    ("divmod", DIVMOD),
+    ("print_int", PRINT_INT),
+    ("read_int", READ_INT),
+    ("memcpy", MEMCPY),
+    ("stack", STACK),
+];
+
+/// Assembles the whole library, `stack` comes last.
+pub fn objects() -> Result<Vec<Object>> {
+    SOURCES.iter().map(|(name, source)| assemble(name, source)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linker::Linker;
+    use crate::memory::MemoryValueType;
+    use crate::virtual_machine::{VirtualMachine, VMState};
+
+    fn run(source: &str, input: &str) -> String {
+        let mut linker = Linker::new();
+        linker.add(assemble("main", source).unwrap());
+        for object in objects().unwrap() {
+            linker.add(object);
+        }
+        let image = linker.link().unwrap();
+
+        let mut vm = VirtualMachine::new(&image.program).unwrap();
+        let mut input = input.bytes();
+        let mut output = String::new();
+        loop {
+            match vm.run().unwrap() {
+                VMState::Halted => return output,
+                _ => match vm.output() {
+                    Ok(c) => output.push(c as u8 as char),
+                    Err(_) => vm.input(input.next().unwrap() as MemoryValueType).unwrap(),
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        let source = "
+            .extern stack, divmod, read_int, print_int
+                    rbo #stack
+                    push #0
+                    call #read_int
+            loop:   jz [rb-1], #end
+                    push #0
+                    call #read_int
+                    push #0
+                    call #read_int
+                    call #divmod
+                    push [rb-2]
+                    call #print_int
+                    rbo #-1
+                    out #32
+                    push [rb-1]
+                    call #print_int
+                    rbo #-3
+                    out #10
+                    add [rb-1], #-1, [rb-1]
+                    jmp #loop
+            end:    halt
+        ";
+        let input = "5\n17 5\n-17 5\n17 -5\n0 3\n1234567890123 1000\n";
+        assert_eq!(run(source, input), "3 2\n-3 -2\n-3 2\n0 0\n1234567890 123\n");
+    }
+
+    #[test]
+    fn test_memcpy() {
+        let source = "
+            .extern stack, memcpy, print_int
+                    rbo #stack
+                    ; three words to copy and room for the copy
+                    push #7
+                    push #8
+                    push #9
+                    rbo #3
+                    push #-6
+                    push #-3
+                    push #3
+                    call #memcpy
+                    rbo #-3
+                    push [rb-3]
+                    call #print_int
+                    push [rb-2]
+                    call #print_int
+                    rbo #-2
+                    halt
+        ";
+        assert_eq!(run(source, ""), "79");
+    }
+}
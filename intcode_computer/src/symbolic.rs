@@ -0,0 +1,591 @@
+use crate::error::{SymbolicError, VMError};
+use crate::memory::MemoryValueType;
+use crate::opcode::{Opcode, ParameterMode, decode_opcode, decode_parameter_modes};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::ops::{Add, Mul, RangeInclusive};
+
+pub type Result<T> = std::result::Result<T, SymbolicError>;
+
+pub type Symbol = usize;
+
+/// Value of a memory cell during symbolic execution. Build expressions with
+/// the operators and constructor functions, they fold constants as far as
+/// possible.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Const(MemoryValueType),
+    Symbol(Symbol),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+    /// Content of memory at a symbolic address. Treated as an unknown value.
+    Load(Box<Expr>),
+}
+
+impl Expr {
+    pub fn constant(val: MemoryValueType) -> Expr {
+        Expr::Const(val)
+    }
+
+    pub fn symbol(symbol: Symbol) -> Expr {
+        Expr::Symbol(symbol)
+    }
+
+    pub fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(x), Expr::Const(y)) => Expr::Const(if x < y {1} else {0}),
+            (a, b) => Expr::LessThan(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn equals(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(x), Expr::Const(y)) => Expr::Const(if x == y {1} else {0}),
+            (ref a, ref b) if a == b => Expr::Const(1),
+            (a, b) => Expr::Equals(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn as_const(&self) -> Option<MemoryValueType> {
+        match self {
+            Expr::Const(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    /// The expression as a linear combination of symbols, if it is one.
+    pub fn linear(&self) -> Option<Linear> {
+        match self {
+            Expr::Const(val) => Some(Linear { coefficients: BTreeMap::new(), constant: *val }),
+            Expr::Symbol(symbol) => {
+                let mut coefficients = BTreeMap::new();
+                coefficients.insert(*symbol, 1);
+                Some(Linear { coefficients, constant: 0 })
+            }
+            Expr::Add(a, b) => Some(a.linear()?.plus(&b.linear()?, 1)),
+            Expr::Mul(a, b) => match (a.as_const(), b.as_const()) {
+                (Some(factor), _) => Some(b.linear()?.scaled(factor)),
+                (_, Some(factor)) => Some(a.linear()?.scaled(factor)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Add for Expr {
+    type Output = Expr;
+
+    fn add(self, other: Expr) -> Expr {
+        match (self, other) {
+            (Expr::Const(x), Expr::Const(y)) => Expr::Const(x + y),
+            (Expr::Const(0), e) | (e, Expr::Const(0)) => e,
+            (a, b) => Expr::Add(Box::new(a), Box::new(b)),
+        }
+    }
+}
+
+impl Mul for Expr {
+    type Output = Expr;
+
+    fn mul(self, other: Expr) -> Expr {
+        match (self, other) {
+            (Expr::Const(x), Expr::Const(y)) => Expr::Const(x * y),
+            (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+            (Expr::Const(1), e) | (e, Expr::Const(1)) => e,
+            (a, b) => Expr::Mul(Box::new(a), Box::new(b)),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(val) => write!(f, "{}", val),
+            Expr::Symbol(symbol) => write!(f, "s{}", symbol),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+            Expr::Load(address) => write!(f, "mem[{}]", address),
+        }
+    }
+}
+
+/// `sum(coefficient * symbol) + constant`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Linear {
+    pub coefficients: BTreeMap<Symbol, MemoryValueType>,
+    pub constant: MemoryValueType,
+}
+
+impl Linear {
+    fn plus(mut self, other: &Linear, factor: MemoryValueType) -> Linear {
+        for (symbol, coefficient) in &other.coefficients {
+            *self.coefficients.entry(*symbol).or_insert(0) += factor * coefficient;
+        }
+        self.coefficients.retain(|_, coefficient| *coefficient != 0);
+        self.constant += factor * other.constant;
+        self
+    }
+
+    fn scaled(mut self, factor: MemoryValueType) -> Linear {
+        for coefficient in self.coefficients.values_mut() {
+            *coefficient *= factor;
+        }
+        self.coefficients.retain(|_, coefficient| *coefficient != 0);
+        self.constant *= factor;
+        self
+    }
+
+    pub fn evaluate(&self, assignment: &BTreeMap<Symbol, MemoryValueType>) -> MemoryValueType {
+        self.coefficients.iter()
+            .map(|(symbol, coefficient)| coefficient * assignment[symbol])
+            .sum::<MemoryValueType>() + self.constant
+    }
+}
+
+/// Condition a path depends on: `expr != 0` if `holds`, `expr == 0` otherwise.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Constraint {
+    pub expr: Expr,
+    pub holds: bool,
+}
+
+impl Constraint {
+    pub fn equals(a: Expr, b: Expr) -> Constraint {
+        Constraint { expr: Expr::equals(a, b), holds: true }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} 0", self.expr, if self.holds {"!="} else {"=="})
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PathEnd {
+    Halted,
+    /// The program wanted more input than was provided
+    Blocked,
+}
+
+/// A fully explored execution path.
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub end: PathEnd,
+    pub constraints: Vec<Constraint>,
+    pub outputs: Vec<Expr>,
+    memory: HashMap<usize, Expr>,
+}
+
+impl Path {
+    /// Content of memory when the path ended.
+    pub fn memory(&self, address: usize) -> Expr {
+        self.memory.get(&address).cloned().unwrap_or(Expr::Const(0))
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    memory: HashMap<usize, Expr>,
+    pc: usize,
+    relative_base: usize,
+    next_input: usize,
+    outputs: Vec<Expr>,
+    constraints: Vec<Constraint>,
+    steps: usize,
+}
+
+impl State {
+    fn load(&self, address: usize) -> Expr {
+        self.memory.get(&address).cloned().unwrap_or(Expr::Const(0))
+    }
+}
+
+enum Operand {
+    Address(usize),
+    Symbolic(Expr),
+}
+
+/// Executes a program on symbolic values, forking at every conditional jump
+/// whose condition depends on a symbol.
+///
+/// Addresses, jump targets and the relative base have to stay concrete. Reads
+/// from symbolic addresses yield `Expr::Load`, anything else that would need
+/// a concrete value fails. Infeasible paths are only pruned when their
+/// condition folds to a constant.
+pub struct SymbolicMachine {
+    initial: State,
+    inputs: Vec<Expr>,
+    max_paths: usize,
+    max_steps: usize,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &[MemoryValueType]) -> Self {
+        let memory = program.iter().enumerate().map(|(i, v)| (i, Expr::Const(*v))).collect();
+        SymbolicMachine {
+            initial: State {
+                memory,
+                pc: 0,
+                relative_base: 0,
+                next_input: 0,
+                outputs: Vec::new(),
+                constraints: Vec::new(),
+                steps: 0,
+            },
+            inputs: Vec::new(),
+            max_paths: 1024,
+            max_steps: 100_000,
+        }
+    }
+
+    pub fn set_memory(&mut self, address: usize, value: Expr) {
+        self.initial.memory.insert(address, value);
+    }
+
+    /// Queues a value to be consumed by the next `In` instruction.
+    pub fn input(&mut self, value: Expr) {
+        self.inputs.push(value);
+    }
+
+    pub fn max_paths(mut self, max_paths: usize) -> Self {
+        self.max_paths = max_paths;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Runs every path to its end.
+    pub fn explore(&self) -> Result<Vec<Path>> {
+        let mut paths = Vec::new();
+        let mut pending = VecDeque::new();
+        pending.push_back(self.initial.clone());
+
+        while let Some(mut state) = pending.pop_front() {
+            loop {
+                if state.steps >= self.max_steps {
+                    return Err(SymbolicError::StepLimit(self.max_steps));
+                }
+                state.steps += 1;
+                match self.step(&mut state)? {
+                    None => continue,
+                    Some(Step::End(end)) => {
+                        paths.push(Path {
+                            end,
+                            constraints: state.constraints,
+                            outputs: state.outputs,
+                            memory: state.memory,
+                        });
+                        break;
+                    }
+                    Some(Step::Fork(other)) => {
+                        if paths.len() + pending.len() + 2 > self.max_paths {
+                            return Err(SymbolicError::PathLimit(self.max_paths));
+                        }
+                        pending.push_back(other);
+                    }
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    fn step(&self, state: &mut State) -> Result<Option<Step>> {
+        let pc = state.pc;
+        let word = state.load(pc).as_const().ok_or(SymbolicError::SymbolicInstruction { pc })?;
+        let opcode = decode_opcode(word)?;
+        let modes = decode_parameter_modes(word)?;
+
+        match opcode {
+            Opcode::Add => self.apply2(state, &modes, Add::add)?,
+            Opcode::Mul => self.apply2(state, &modes, Mul::mul)?,
+            Opcode::LT => self.apply2(state, &modes, Expr::less_than)?,
+            Opcode::EQ => self.apply2(state, &modes, Expr::equals)?,
+            Opcode::In => {
+                let value = match self.inputs.get(state.next_input) {
+                    Some(value) => value.clone(),
+                    None => return Ok(Some(Step::End(PathEnd::Blocked))),
+                };
+                let address = self.destination(state, &modes, 0)?;
+                state.memory.insert(address, value);
+                state.next_input += 1;
+                state.pc += 2;
+            }
+            Opcode::Out => {
+                let value = self.param(state, &modes, 0)?;
+                state.outputs.push(value);
+                state.pc += 2;
+            }
+            Opcode::JNZ | Opcode::JZ => {
+                let condition = self.param(state, &modes, 0)?;
+                let target = self.param(state, &modes, 1)?;
+                let jump_if = opcode == Opcode::JNZ;
+                let jump = |state: &mut State| -> Result<()> {
+                    match target.as_const() {
+                        Some(target) if target >= 0 => state.pc = target as usize,
+                        Some(_) => return Err(VMError::NegativeAddress.into()),
+                        None => return Err(SymbolicError::SymbolicJump { pc }),
+                    }
+                    Ok(())
+                };
+
+                match condition.as_const() {
+                    Some(val) if (val != 0) == jump_if => jump(state)?,
+                    Some(_) => state.pc += 3,
+                    None => {
+                        let mut other = state.clone();
+                        state.constraints.push(Constraint { expr: condition.clone(), holds: jump_if });
+                        jump(state)?;
+                        other.constraints.push(Constraint { expr: condition, holds: !jump_if });
+                        other.pc += 3;
+                        return Ok(Some(Step::Fork(other)));
+                    }
+                }
+            }
+            Opcode::RBO => {
+                let offset = self.param(state, &modes, 0)?
+                    .as_const()
+                    .ok_or(SymbolicError::SymbolicRelativeBase { pc })?;
+                let new_base = state.relative_base as MemoryValueType + offset;
+                if new_base < 0 {
+                    return Err(VMError::NegativeAddress.into());
+                }
+                state.relative_base = new_base as usize;
+                state.pc += 2;
+            }
+            Opcode::Halt => return Ok(Some(Step::End(PathEnd::Halted))),
+        }
+        Ok(None)
+    }
+
+    fn operand(&self, state: &State, modes: &[ParameterMode; 3], offset: usize) -> Result<Operand> {
+        let raw = state.load(state.pc + offset + 1);
+        let base = match modes[offset] {
+            ParameterMode::Immediate => return Ok(Operand::Address(state.pc + offset + 1)),
+            ParameterMode::Position => 0,
+            ParameterMode::Relative => state.relative_base as MemoryValueType,
+        };
+        match raw.as_const() {
+            Some(val) if val + base < 0 => Err(VMError::NegativeAddress.into()),
+            Some(val) => Ok(Operand::Address((val + base) as usize)),
+            None => Ok(Operand::Symbolic(raw + Expr::Const(base))),
+        }
+    }
+
+    fn param(&self, state: &State, modes: &[ParameterMode; 3], offset: usize) -> Result<Expr> {
+        Ok(match self.operand(state, modes, offset)? {
+            Operand::Address(address) => state.load(address),
+            Operand::Symbolic(address) => Expr::Load(Box::new(address)),
+        })
+    }
+
+    fn destination(&self, state: &State, modes: &[ParameterMode; 3], offset: usize) -> Result<usize> {
+        if modes[offset] == ParameterMode::Immediate {
+            return Err(VMError::ImmediateDestination.into());
+        }
+        match self.operand(state, modes, offset)? {
+            Operand::Address(address) => Ok(address),
+            Operand::Symbolic(_) => Err(SymbolicError::SymbolicWrite { pc: state.pc }),
+        }
+    }
+
+    fn apply2(&self, state: &mut State, modes: &[ParameterMode; 3], f: fn(Expr, Expr) -> Expr) -> Result<()> {
+        let address = self.destination(state, modes, 2)?;
+        let value = f(self.param(state, modes, 0)?, self.param(state, modes, 1)?);
+        state.memory.insert(address, value);
+        state.pc += 4;
+        Ok(())
+    }
+}
+
+enum Step {
+    End(PathEnd),
+    /// The current state took one branch, this is the other one
+    Fork(State),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Relation {
+    Zero,
+    NonZero,
+    Negative,
+    NonNegative,
+}
+
+impl Relation {
+    fn holds(self, val: MemoryValueType) -> bool {
+        match self {
+            Relation::Zero => val == 0,
+            Relation::NonZero => val != 0,
+            Relation::Negative => val < 0,
+            Relation::NonNegative => val >= 0,
+        }
+    }
+}
+
+fn normalize(constraint: &Constraint) -> Result<(Linear, Relation)> {
+    let non_linear = || SymbolicError::NonLinear(constraint.to_string());
+    let (linear, holds, otherwise) = match &constraint.expr {
+        Expr::Equals(a, b) => {
+            let difference = a.linear().ok_or_else(non_linear)?.plus(&b.linear().ok_or_else(non_linear)?, -1);
+            (difference, Relation::Zero, Relation::NonZero)
+        }
+        Expr::LessThan(a, b) => {
+            let difference = a.linear().ok_or_else(non_linear)?.plus(&b.linear().ok_or_else(non_linear)?, -1);
+            (difference, Relation::Negative, Relation::NonNegative)
+        }
+        expr => (expr.linear().ok_or_else(non_linear)?, Relation::NonZero, Relation::Zero),
+    };
+    Ok((linear, if constraint.holds { holds } else { otherwise }))
+}
+
+/// Finds values for all symbols within their domains that satisfy every
+/// constraint, or `None` if there are none.
+///
+/// Only linear constraints are supported. If one of them is an equation, the
+/// last symbol in it is solved for directly and only the remaining symbols are
+/// enumerated, so two symbols with a hundred values each need a hundred
+/// candidate checks instead of ten thousand.
+pub fn solve(constraints: &[Constraint], domains: &BTreeMap<Symbol, RangeInclusive<MemoryValueType>>)
+    -> Result<Option<BTreeMap<Symbol, MemoryValueType>>> {
+    let relations = constraints.iter().map(normalize).collect::<Result<Vec<_>>>()?;
+    for (linear, _) in &relations {
+        for symbol in linear.coefficients.keys() {
+            if !domains.contains_key(symbol) {
+                return Err(SymbolicError::UnboundedSymbol(*symbol));
+            }
+        }
+    }
+
+    let pivot = relations.iter()
+        .filter(|(_, relation)| *relation == Relation::Zero)
+        .filter_map(|(linear, _)| linear.coefficients.iter().next_back().map(|(s, c)| (*s, *c, linear)))
+        .next();
+    let enumerated: Vec<Symbol> = domains.keys().cloned()
+        .filter(|symbol| pivot.map(|(p, _, _)| p != *symbol).unwrap_or(true))
+        .collect();
+
+    let mut assignment = BTreeMap::new();
+    Ok(search(&enumerated, domains, &mut assignment, &mut |assignment| {
+        if let Some((symbol, coefficient, linear)) = pivot {
+            // coefficient * symbol + rest == 0
+            let rest = linear.evaluate_without(symbol, assignment);
+            if rest % coefficient != 0 {
+                return false;
+            }
+            let val = -rest / coefficient;
+            if !domains[&symbol].contains(&val) {
+                return false;
+            }
+            assignment.insert(symbol, val);
+        }
+        relations.iter().all(|(linear, relation)| relation.holds(linear.evaluate(assignment)))
+    }))
+}
+
+impl Linear {
+    fn evaluate_without(&self, excluded: Symbol, assignment: &BTreeMap<Symbol, MemoryValueType>) -> MemoryValueType {
+        self.coefficients.iter()
+            .filter(|(symbol, _)| **symbol != excluded)
+            .map(|(symbol, coefficient)| coefficient * assignment[symbol])
+            .sum::<MemoryValueType>() + self.constant
+    }
+}
+
+fn search(symbols: &[Symbol], domains: &BTreeMap<Symbol, RangeInclusive<MemoryValueType>>,
+          assignment: &mut BTreeMap<Symbol, MemoryValueType>,
+          check: &mut dyn FnMut(&mut BTreeMap<Symbol, MemoryValueType>) -> bool)
+    -> Option<BTreeMap<Symbol, MemoryValueType>> {
+    match symbols.split_first() {
+        None => if check(assignment) { Some(assignment.clone()) } else { None },
+        Some((symbol, rest)) => {
+            for val in domains[symbol].clone() {
+                assignment.insert(*symbol, val);
+                if let Some(solution) = search(rest, domains, assignment, check) {
+                    return Some(solution);
+                }
+            }
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear() {
+        let expr = Expr::symbol(0) * Expr::constant(3) + (Expr::symbol(1) + Expr::constant(7));
+        let linear = expr.linear().unwrap();
+        assert_eq!(linear.coefficients.into_iter().collect::<Vec<_>>(), vec![(0, 3), (1, 1)]);
+        assert_eq!(linear.constant, 7);
+        assert_eq!((Expr::symbol(0) * Expr::symbol(1)).linear(), None);
+    }
+
+    #[test]
+    fn test_solve_noun_and_verb() {
+        // mem[0] = (mem[1] + mem[2]) * 3 + mem[2] with noun and verb in mem[1] and mem[2]
+        let program = vec![1,0,0,20,1,1,2,20,1002,20,3,20,1,20,2,0,99];
+        let mut machine = SymbolicMachine::new(&program);
+        machine.set_memory(1, Expr::symbol(0));
+        machine.set_memory(2, Expr::symbol(1));
+        let paths = machine.explore().unwrap();
+        assert_eq!(paths.len(), 1);
+
+        let mut domains = BTreeMap::new();
+        domains.insert(0, 0..=99);
+        domains.insert(1, 0..=99);
+        let target = 3 * (12 + 34) + 34;
+        let solution = solve(&[Constraint::equals(paths[0].memory(0), Expr::constant(target))], &domains)
+            .unwrap()
+            .unwrap();
+        assert_eq!(3 * (solution[&0] + solution[&1]) + solution[&1], target);
+    }
+
+    #[test]
+    fn test_forks() {
+        // day 05 example: outputs 0 if the input is zero, 1 otherwise
+        let program = vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9];
+        let mut machine = SymbolicMachine::new(&program);
+        machine.input(Expr::symbol(0));
+        let paths = machine.explore().unwrap();
+        assert_eq!(paths.len(), 2);
+
+        let mut domains = BTreeMap::new();
+        domains.insert(0, -10..=10);
+        for path in &paths {
+            assert_eq!(path.end, PathEnd::Halted);
+            let solution = solve(&path.constraints, &domains).unwrap().unwrap();
+            let expected = if solution[&0] == 0 {0} else {1};
+            assert_eq!(path.outputs, vec![Expr::constant(expected)]);
+        }
+
+        let mut machine = SymbolicMachine::new(&[3,0,99]);
+        assert_eq!(machine.explore().unwrap()[0].end, PathEnd::Blocked);
+        machine.input(Expr::constant(1));
+        assert_eq!(machine.explore().unwrap()[0].end, PathEnd::Halted);
+    }
+
+    #[test]
+    fn test_solve_inequalities() {
+        let constraints = vec![
+            Constraint { expr: Expr::less_than(Expr::symbol(0), Expr::constant(5)), holds: false },
+            Constraint { expr: Expr::symbol(0) + Expr::constant(-6), holds: true },
+        ];
+        let mut domains = BTreeMap::new();
+        domains.insert(0, 0..=10);
+        assert_eq!(solve(&constraints, &domains).unwrap().unwrap()[&0], 5);
+
+        domains.insert(0, 0..=4);
+        assert!(solve(&constraints, &domains).unwrap().is_none());
+    }
+}
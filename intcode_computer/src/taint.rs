@@ -0,0 +1,155 @@
+use std::collections::{BTreeSet, HashMap};
+
+/// Set of input labels a value depends on. Label `n` stands for the n-th value
+/// passed to `VirtualMachine::input` after tracking was enabled.
+pub type Taint = BTreeSet<usize>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BranchTaint {
+    /// Address of the conditional jump
+    pub address: usize,
+    pub taken: bool,
+    /// Labels of the condition
+    pub taint: Taint,
+}
+
+/// Shadow state for dynamic taint analysis.
+///
+/// Only explicit data flow is tracked: results of `Add`, `Mul`, `LT` and `EQ`
+/// carry the union of their operands' labels and `In` stores the label of the
+/// consumed input. Immediate operands carry the labels of the code cell they
+/// are read from, so self-modifying code is handled. Influence through
+/// computed addresses or the relative base is not tracked.
+#[derive(Default)]
+pub struct TaintTracker {
+    memory: HashMap<usize, Taint>,
+    next_label: usize,
+    pending_input: Option<usize>,
+    outputs: Vec<Taint>,
+    branches: Vec<BranchTaint>,
+}
+
+impl TaintTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Labels of the values emitted so far, in output order.
+    pub fn outputs(&self) -> &[Taint] {
+        &self.outputs
+    }
+
+    /// Every conditional jump executed so far, in execution order.
+    pub fn branches(&self) -> &[BranchTaint] {
+        &self.branches
+    }
+
+    /// Labels of the value currently stored at the given address.
+    pub fn memory(&self, address: usize) -> Taint {
+        self.memory.get(&address).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn label_input(&mut self) {
+        self.pending_input = Some(self.next_label);
+        self.next_label += 1;
+    }
+
+    pub(crate) fn consume_input(&mut self, address: usize) {
+        let taint = self.pending_input.take().into_iter().collect();
+        self.store(address, taint);
+    }
+
+    pub(crate) fn propagate(&mut self, sources: &[usize], destination: usize) {
+        let taint = self.union(sources);
+        self.store(destination, taint);
+    }
+
+    pub(crate) fn output(&mut self, source: usize) {
+        let taint = self.memory(source);
+        self.outputs.push(taint);
+    }
+
+    pub(crate) fn branch(&mut self, address: usize, taken: bool, condition: usize) {
+        let taint = self.memory(condition);
+        self.branches.push(BranchTaint { address, taken, taint });
+    }
+
+    /// Values written from outside the machine do not depend on any input.
+    pub(crate) fn clear(&mut self, address: usize) {
+        self.memory.remove(&address);
+    }
+
+    fn union(&self, sources: &[usize]) -> Taint {
+        sources.iter()
+            .filter_map(|address| self.memory.get(address))
+            .flat_map(|taint| taint.iter().cloned())
+            .collect()
+    }
+
+    fn store(&mut self, address: usize, taint: Taint) {
+        if taint.is_empty() {
+            self.memory.remove(&address);
+        } else {
+            self.memory.insert(address, taint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_machine::{VirtualMachine, VMState};
+
+    fn labels(labels: &[usize]) -> Taint {
+        labels.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_propagation() {
+        // out(in0 + in1), out(in1 * 3), out(7)
+        let program = vec![3,100,3,101,1,100,101,102,4,102,1002,101,3,103,4,103,104,7,99];
+        let mut vm = VirtualMachine::new(&program).unwrap();
+        vm.enable_taint_tracking();
+
+        vm.input(4).unwrap();
+        assert_eq!(vm.run().unwrap(), VMState::Blocked);
+        vm.input(5).unwrap();
+        let mut outputs = Vec::new();
+        while vm.run().unwrap() != VMState::Halted {
+            outputs.push(vm.output().unwrap());
+        }
+
+        assert_eq!(outputs, vec![9, 15, 7]);
+        let taint = vm.taint().unwrap();
+        assert_eq!(taint.outputs(), &[labels(&[0, 1]), labels(&[1]), labels(&[])]);
+        assert_eq!(taint.memory(102), labels(&[0, 1]));
+
+        vm[102] = 0;
+        assert_eq!(vm.taint().unwrap().memory(102), labels(&[]));
+    }
+
+    #[test]
+    fn test_branches() {
+        // day 05 example: outputs 1 if the input is less than 8, 0 otherwise
+        let program = vec![3,3,1107,-1,8,3,4,3,99];
+        let mut vm = VirtualMachine::new(&program).unwrap();
+        vm.enable_taint_tracking();
+        vm.input(3).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.output().unwrap(), 1);
+        // the input overwrote an immediate operand
+        assert_eq!(vm.taint().unwrap().outputs(), &[labels(&[0])]);
+
+        // if input != 0 { out(1) } else { out(2) }
+        let program = vec![3,11,1005,11,7,104,2,104,1,99,0,0];
+        let mut vm = VirtualMachine::new(&program).unwrap();
+        vm.enable_taint_tracking();
+        vm.input(1).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.output().unwrap(), 1);
+        assert_eq!(vm.taint().unwrap().branches(), &[
+            BranchTaint { address: 2, taken: true, taint: labels(&[0]) }
+        ]);
+        assert_eq!(vm.taint().unwrap().outputs(), &[labels(&[])]);
+    }
+}
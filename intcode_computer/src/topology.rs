@@ -0,0 +1,286 @@
+use crate::error::TopologyError;
+use crate::memory::MemoryValueType;
+use crate::opcode::{decode_opcode, Opcode};
+use crate::virtual_machine::{VirtualMachine, VMState};
+use std::collections::VecDeque;
+
+pub type Result<T> = std::result::Result<T, TopologyError>;
+
+/// How long a machine runs before the next one gets its turn.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Policy {
+    /// One instruction per turn
+    RoundRobin,
+    /// Until the machine halts or waits for input
+    RunUntilBlock,
+    /// At most the given number of instructions
+    Quantum(usize),
+}
+
+/// A machine executed `steps` instructions, starting at `clock`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Turn {
+    pub machine: usize,
+    pub clock: u64,
+    pub steps: usize,
+}
+
+struct Node {
+    vm: VirtualMachine,
+    queue: VecDeque<MemoryValueType>,
+    targets: Vec<usize>,
+    outputs: Vec<MemoryValueType>,
+}
+
+enum Outcome {
+    Stepped,
+    Waiting,
+    Halted,
+}
+
+/// A network of machines whose outputs feed the inputs of others.
+///
+/// Every output is appended to the input queue of each machine it is
+/// connected to, so a machine can feed several machines and several machines
+/// can feed one. The machines run on a single thread and take turns in the
+/// order they were added, how long a turn lasts is up to the `Policy`. The
+/// interleaving only depends on the policy, so every run of the same network
+/// gives the same result and trace.
+///
+/// ```
+/// use intcode_computer::topology::Topology;
+///
+/// // adds one to every input
+/// let program = [3,11, 1001,11,1,11, 4,11, 1105,1,0, 0];
+/// let mut topology = Topology::new();
+/// let source = topology.node(&program, &[1, 2]).unwrap();
+/// let left = topology.node(&program, &[]).unwrap();
+/// let right = topology.node(&program, &[]).unwrap();
+/// topology.edge(source, left).unwrap();
+/// topology.edge(source, right).unwrap();
+/// // every machine waits for more input at the end
+/// assert!(topology.run().is_err());
+/// assert_eq!(topology.outputs(left).unwrap(), &[3, 4]);
+/// assert_eq!(topology.outputs(right).unwrap(), &[3, 4]);
+/// ```
+pub struct Topology {
+    policy: Policy,
+    nodes: Vec<Node>,
+    trace: Vec<Turn>,
+    clock: u64,
+}
+
+impl Default for Topology {
+    fn default() -> Topology {
+        Topology::new()
+    }
+}
+
+impl Topology {
+    /// A network whose machines run until they block.
+    pub fn new() -> Topology {
+        Topology::with_policy(Policy::RunUntilBlock)
+    }
+
+    pub fn with_policy(policy: Policy) -> Topology {
+        Topology { policy, nodes: Vec::new(), trace: Vec::new(), clock: 0 }
+    }
+
+    /// Adds a machine that first reads the given inputs and returns its id.
+    pub fn node(&mut self, program: &[MemoryValueType], inputs: &[MemoryValueType]) -> Result<usize> {
+        let machine = self.nodes.len();
+        let vm = VirtualMachine::new(program).map_err(|error| TopologyError::Machine { machine, error })?;
+        self.add(vm);
+        self.feed(machine, inputs)?;
+        Ok(machine)
+    }
+
+    /// Adds a machine that has already been set up and returns its id.
+    pub fn add(&mut self, vm: VirtualMachine) -> usize {
+        self.nodes.push(Node { vm, queue: VecDeque::new(), targets: Vec::new(), outputs: Vec::new() });
+        self.nodes.len() - 1
+    }
+
+    /// Sends the outputs of `from` to `to`.
+    pub fn edge(&mut self, from: usize, to: usize) -> Result<()> {
+        self.get(to)?;
+        self.get_mut(from)?.targets.push(to);
+        Ok(())
+    }
+
+    /// Queues inputs for a machine, also between runs.
+    pub fn feed(&mut self, machine: usize, inputs: &[MemoryValueType]) -> Result<()> {
+        self.get_mut(machine)?.queue.extend(inputs);
+        Ok(())
+    }
+
+    pub fn machine(&self, machine: usize) -> Result<&VirtualMachine> {
+        Ok(&self.get(machine)?.vm)
+    }
+
+    /// Everything a machine has output so far.
+    pub fn outputs(&self, machine: usize) -> Result<&[MemoryValueType]> {
+        Ok(&self.get(machine)?.outputs)
+    }
+
+    pub fn is_halted(&self, machine: usize) -> Result<bool> {
+        Ok(self.get(machine)?.vm.state() == VMState::Halted)
+    }
+
+    /// The turns so far in the order they were taken.
+    pub fn trace(&self) -> &[Turn] {
+        &self.trace
+    }
+
+    /// Instructions executed by all machines together.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    fn get(&self, machine: usize) -> Result<&Node> {
+        self.nodes.get(machine).ok_or(TopologyError::UnknownMachine(machine))
+    }
+
+    fn get_mut(&mut self, machine: usize) -> Result<&mut Node> {
+        self.nodes.get_mut(machine).ok_or(TopologyError::UnknownMachine(machine))
+    }
+
+    /// Runs the network until every machine has halted. Fails with
+    /// `Deadlock` if the remaining machines all wait for input and none is
+    /// queued, the network can be run again after feeding them.
+    pub fn run(&mut self) -> Result<()> {
+        let quantum = match self.policy {
+            Policy::RoundRobin => 1,
+            Policy::RunUntilBlock => usize::max_value(),
+            Policy::Quantum(steps) => steps.max(1),
+        };
+        loop {
+            let mut progress = false;
+            for machine in 0..self.nodes.len() {
+                let clock = self.clock;
+                let mut steps = 0;
+                while steps < quantum {
+                    match self.step(machine)? {
+                        Outcome::Stepped => steps += 1,
+                        Outcome::Waiting | Outcome::Halted => break,
+                    }
+                }
+                if steps > 0 {
+                    self.clock += steps as u64;
+                    self.trace.push(Turn { machine, clock, steps });
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+
+        let blocked: Vec<usize> = (0..self.nodes.len())
+            .filter(|&machine| self.nodes[machine].vm.state() != VMState::Halted)
+            .collect();
+        if blocked.is_empty() {
+            Ok(())
+        } else {
+            Err(TopologyError::Deadlock(blocked))
+        }
+    }
+
+    /// Executes one instruction unless the machine has halted or needs input
+    /// that is not there.
+    fn step(&mut self, machine: usize) -> Result<Outcome> {
+        let error = |error| TopologyError::Machine { machine, error };
+        let node = &mut self.nodes[machine];
+        let vm = &mut node.vm;
+        if vm.state() == VMState::Halted {
+            return Ok(Outcome::Halted);
+        }
+        let reads = decode_opcode(vm[vm.pc()]).map_err(error)? == Opcode::In;
+        if reads && vm.input_register().is_none() {
+            match node.queue.pop_front() {
+                Some(value) => vm.input(value).map_err(error)?,
+                None => return Ok(Outcome::Waiting),
+            }
+        }
+        vm.resume();
+        vm.step().map_err(error)?;
+
+        if vm.output_register().is_some() {
+            let value = vm.output().map_err(error)?;
+            node.outputs.push(value);
+            for target in 0..node.targets.len() {
+                let target = self.nodes[machine].targets[target];
+                self.nodes[target].queue.push_back(value);
+            }
+        }
+        Ok(Outcome::Stepped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{ECHO, FEEDBACK};
+
+    #[test]
+    fn test_feedback_loop() {
+        let mut topology = Topology::new();
+        let amplifiers: Vec<usize> = [9, 8, 7, 6, 5].iter().map(|&phase| topology.node(FEEDBACK, &[phase]).unwrap()).collect();
+        topology.feed(amplifiers[0], &[0]).unwrap();
+        for (i, &from) in amplifiers.iter().enumerate() {
+            topology.edge(from, amplifiers[(i + 1) % amplifiers.len()]).unwrap();
+        }
+        topology.run().unwrap();
+        assert_eq!(topology.outputs(amplifiers[4]).unwrap().last(), Some(&139629729));
+        assert!(amplifiers.iter().all(|&amplifier| topology.is_halted(amplifier).unwrap()));
+    }
+
+    #[test]
+    fn test_policies() {
+        // two producers output three 1s and three 2s into a consumer that
+        // echoes six inputs
+        let mut consumer: Vec<MemoryValueType> = [3,25, 4,25].iter().cycle().take(24).cloned().collect();
+        consumer.extend(&[99, 0]);
+        let policies = [Policy::RoundRobin, Policy::RunUntilBlock, Policy::Quantum(2)];
+        let expected: [&[MemoryValueType]; 3] = [&[1, 2, 1, 2, 1, 2], &[1, 1, 1, 2, 2, 2], &[1, 1, 2, 2, 1, 2]];
+        for (&policy, &outputs) in policies.iter().zip(&expected) {
+            let mut topology = Topology::with_policy(policy);
+            topology.node(&[104,1, 104,1, 104,1, 99], &[]).unwrap();
+            topology.node(&[104,2, 104,2, 104,2, 99], &[]).unwrap();
+            topology.node(&consumer, &[]).unwrap();
+            topology.edge(0, 2).unwrap();
+            topology.edge(1, 2).unwrap();
+            topology.run().unwrap();
+            assert_eq!(topology.outputs(2).unwrap(), outputs);
+            assert_eq!(topology.clock(), 4 + 4 + 13);
+
+            let first_turns = match policy {
+                Policy::RoundRobin => [(0, 0, 1), (1, 1, 1), (2, 2, 1)],
+                Policy::RunUntilBlock => [(0, 0, 4), (1, 4, 4), (2, 8, 13)],
+                Policy::Quantum(_) => [(0, 0, 2), (1, 2, 2), (2, 4, 2)],
+            };
+            let turns: Vec<_> = topology.trace().iter().take(3).map(|turn| (turn.machine, turn.clock, turn.steps)).collect();
+            assert_eq!(turns, first_turns);
+        }
+    }
+
+    #[test]
+    fn test_deadlock() {
+        // both wait for the other before they output anything
+        let mut topology = Topology::new();
+        topology.node(ECHO, &[]).unwrap();
+        topology.node(ECHO, &[]).unwrap();
+        topology.edge(0, 1).unwrap();
+        topology.edge(1, 0).unwrap();
+        assert_eq!(topology.run().unwrap_err().to_string(), "Deadlock, machines [0, 1] wait for input that never comes");
+        assert!(topology.trace().is_empty());
+
+        topology.feed(0, &[7]).unwrap();
+        topology.run().unwrap();
+        assert_eq!(topology.outputs(1).unwrap(), &[7]);
+
+        topology.edge(1, 2).expect_err("there is no machine 2");
+        topology.node(&[42], &[]).unwrap();
+        assert_eq!(topology.run().unwrap_err().to_string(), "Machine 2: Unknown opcode");
+    }
+}
@@ -1,17 +1,116 @@
+use crate::error::ParseError;
 use crate::memory::MemoryValueType;
 
-pub fn string_to_program(s: &str) -> Vec<MemoryValueType> {
-    // TODO: no unwrap
-    s.trim().split(',').map(|n| n.trim().parse::<MemoryValueType>().unwrap()).collect()
+/// Parses a comma separated program.
+///
+/// Whitespace and blank lines between values are ignored, as are comments
+/// starting with `#` and running to the end of the line. A trailing comma is
+/// allowed. The input is scanned in place, the only allocation is the program
+/// itself.
+pub fn string_to_program(s: &str) -> Result<Vec<MemoryValueType>, ParseError> {
+    let bytes = s.as_bytes();
+    let mut program = Vec::with_capacity(bytes.iter().filter(|&&b| b == b',').count() + 1);
+    let mut pos = skip_trivia(bytes, 0);
+
+    while pos < bytes.len() {
+        let index = program.len();
+        let start = pos;
+        if bytes[pos] == b',' {
+            return Err(ParseError::MissingValue { index, offset: start });
+        }
+
+        let negative = bytes[pos] == b'-';
+        if negative || bytes[pos] == b'+' {
+            pos += 1;
+        }
+        let digits = pos;
+        let mut value: MemoryValueType = 0;
+        let mut overflow = false;
+        while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+            let digit = MemoryValueType::from(bytes[pos] - b'0');
+            let next = value.checked_mul(10).and_then(|v| {
+                if negative { v.checked_sub(digit) } else { v.checked_add(digit) }
+            });
+            match next {
+                Some(next) => value = next,
+                None => overflow = true,
+            }
+            pos += 1;
+        }
+
+        let end = token_end(bytes, pos);
+        if digits == pos || end != pos {
+            return Err(ParseError::InvalidValue { token: s[start..end].to_string(), index, offset: start });
+        }
+        if overflow {
+            return Err(ParseError::OutOfRange { token: s[start..end].to_string(), index, offset: start });
+        }
+        program.push(value);
+
+        pos = skip_trivia(bytes, pos);
+        if pos < bytes.len() {
+            if bytes[pos] != b',' {
+                let token = s[pos..token_end(bytes, pos)].to_string();
+                return Err(ParseError::MissingSeparator { token, index: index + 1, offset: pos });
+            }
+            pos = skip_trivia(bytes, pos + 1);
+        }
+    }
+    Ok(program)
+}
+
+fn skip_trivia(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'#' => while pos < bytes.len() && bytes[pos] != b'\n' {
+                pos += 1;
+            },
+            b if b.is_ascii_whitespace() => pos += 1,
+            _ => break,
+        }
+    }
+    pos
+}
+
+fn token_end(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos] != b',' && bytes[pos] != b'#' && !bytes[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    
+
     #[test]
     fn simple_test() {
         let s = " 1,2,0, 5135, 120, 331,  3 , 14";
-        assert_eq!(string_to_program(s), vec![1,2,0,5135,120,331,3,14]);
+        assert_eq!(string_to_program(s).unwrap(), vec![1,2,0,5135,120,331,3,14]);
+    }
+
+    #[test]
+    fn test_layout() {
+        let s = "# day 05 example\n3,9,8,9,10,9,  # compare\n\n4,9,99,\n-1,8,\n";
+        assert_eq!(string_to_program(s).unwrap(), vec![3,9,8,9,10,9,4,9,99,-1,8]);
+        assert_eq!(string_to_program("").unwrap(), vec![]);
+        assert_eq!(string_to_program(" # nothing\n").unwrap(), vec![]);
+        assert_eq!(string_to_program("+5,-9223372036854775808").unwrap(), vec![5, MemoryValueType::min_value()]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(string_to_program("1,2,x3,4"),
+                   Err(ParseError::InvalidValue { token: "x3".to_string(), index: 2, offset: 4 }));
+        assert_eq!(string_to_program("1,2,3ä\n"),
+                   Err(ParseError::InvalidValue { token: "3ä".to_string(), index: 2, offset: 4 }));
+        assert_eq!(string_to_program("1,-"),
+                   Err(ParseError::InvalidValue { token: "-".to_string(), index: 1, offset: 2 }));
+        assert_eq!(string_to_program("1,,2"),
+                   Err(ParseError::MissingValue { index: 1, offset: 2 }));
+        assert_eq!(string_to_program("1\n2"),
+                   Err(ParseError::MissingSeparator { token: "2".to_string(), index: 1, offset: 2 }));
+        assert_eq!(string_to_program("0,99999999999999999999"),
+                   Err(ParseError::OutOfRange { token: "99999999999999999999".to_string(), index: 1, offset: 2 }));
     }
 }
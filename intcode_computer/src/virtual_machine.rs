@@ -1,7 +1,7 @@
-use crate::opcode::{Opcode, ParameterMode};
+use crate::opcode::{Opcode, ParameterMode, decode_opcode, decode_parameter_modes};
 use crate::error::*;
 use crate::memory::{Memory, MemoryValueType};
-use std::convert::TryFrom;
+use crate::taint::TaintTracker;
 use std::ops::{Add, Mul};
 use log::{debug};
 use std::fmt::{Display, Debug};
@@ -15,6 +15,18 @@ pub enum VMState {
     Halted
 }
 
+/// Memory locations an instruction touches, see `VirtualMachine::next_accesses`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Accesses {
+    /// Operand words of the instruction itself
+    pub operands: Vec<usize>,
+    /// Cells read through position or relative parameters
+    pub reads: Vec<usize>,
+    pub write: Option<usize>,
+    pub uses_relative_base: bool,
+    pub sets_relative_base: bool,
+}
+
 pub struct VirtualMachine {
     // TODO: I originally wanted to have a byte memory for space efficiency.
     // Sadly, that makes operand parsing non trivial so I opted for MemoryValueType's for
@@ -24,7 +36,8 @@ pub struct VirtualMachine {
     relative_base: usize,
     state: VMState,
     input_register: Option<MemoryValueType>,
-    output_register: Option<MemoryValueType>
+    output_register: Option<MemoryValueType>,
+    taint: Option<TaintTracker>
 }
 
 impl VirtualMachine {
@@ -37,20 +50,84 @@ impl VirtualMachine {
             relative_base: 0,
             state: VMState::Paused,
             input_register: None,
-            output_register: None
+            output_register: None,
+            taint: None
         })
     }
 
+    /// Starts labelling every subsequent input and propagating the labels
+    /// through the machine (see `TaintTracker`).
+    pub fn enable_taint_tracking(&mut self) {
+        self.taint = Some(TaintTracker::new());
+    }
+
+    pub fn taint(&self) -> Option<&TaintTracker> {
+        self.taint.as_ref()
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn relative_base(&self) -> usize {
+        self.relative_base
+    }
+
+    pub fn state(&self) -> VMState {
+        self.state
+    }
+
+    /// Input that has been provided but not yet consumed by an `In`.
+    pub fn input_register(&self) -> Option<MemoryValueType> {
+        self.input_register
+    }
+
+    /// Output that has been produced but not yet taken.
+    pub fn output_register(&self) -> Option<MemoryValueType> {
+        self.output_register
+    }
+
+    /// Memory the next instruction touches when it is executed.
+    pub fn next_accesses(&self) -> Result<Accesses> {
+        let opcode = self.opcode()?;
+        let modes = self.parameter_modes()?;
+        let mut accesses = Accesses::default();
+        let count = opcode.parameter_count();
+        let destination = opcode.destination();
+
+        // the target of a jump is only read if the jump is taken
+        let used = match opcode {
+            Opcode::JNZ if self.param(0)? == 0 => 1,
+            Opcode::JZ if self.param(0)? != 0 => 1,
+            _ => count,
+        };
+        for (offset, mode) in modes.iter().enumerate().take(used) {
+            accesses.operands.push(self.pc + offset + 1);
+            match mode {
+                ParameterMode::Immediate => continue,
+                ParameterMode::Relative => accesses.uses_relative_base = true,
+                ParameterMode::Position => (),
+            }
+            let address = self.param_address(offset)?;
+            if destination == Some(offset) {
+                accesses.write = Some(address);
+            } else {
+                accesses.reads.push(address);
+            }
+        }
+        if opcode == Opcode::RBO {
+            accesses.uses_relative_base = true;
+            accesses.sets_relative_base = true;
+        }
+        Ok(accesses)
+    }
+
     fn opcode(&self) -> Result<Opcode> {
-        let opcode = Opcode::try_from((self.memory[self.pc] % 100) as u8)?;
-        Ok(opcode)
+        decode_opcode(self.memory[self.pc])
     }
 
     fn parameter_modes(&self) -> Result<[ParameterMode; 3]> {
-        let fst = ParameterMode::try_from(((self.memory[self.pc] /   100) % 10) as u8)?;
-        let snd = ParameterMode::try_from(((self.memory[self.pc] /  1000) % 10) as u8)?;
-        let thd = ParameterMode::try_from(((self.memory[self.pc] / 10000) % 10) as u8)?;
-        Ok([fst, snd, thd])
+        decode_parameter_modes(self.memory[self.pc])
     }
 
     pub fn run(&mut self) -> Result<VMState> {
@@ -81,6 +158,9 @@ impl VirtualMachine {
             return Err(VMError::InputAlreadyPopulated);
         }
         self.input_register = Some(val);
+        if let Some(taint) = &mut self.taint {
+            taint.label_input();
+        }
         Ok(())
     }
 
@@ -91,6 +171,15 @@ impl VirtualMachine {
     }
 
 
+    /// Lets a blocked machine be single stepped again once its output has
+    /// been taken or its input provided. A machine that still waits for
+    /// input blocks again on its next step.
+    pub fn resume(&mut self) {
+        if self.state == VMState::Blocked {
+            self.state = VMState::Paused;
+        }
+    }
+
     pub fn step(&mut self) -> Result<()> {
         if self.state == VMState::Halted {
             return Err(VMError::MachineHalted)
@@ -123,6 +212,9 @@ impl VirtualMachine {
                     let in_address = self.param_address(0)?;
                     self.memory[in_address] = val;
                     self.input_register = None;
+                    if let Some(taint) = &mut self.taint {
+                        taint.consume_input(in_address);
+                    }
                     self.pc += 2;
                 } else {
                     // No Value there, block
@@ -130,7 +222,11 @@ impl VirtualMachine {
                 }
             }
             Opcode::Out => {
-                self.output_register = Some(self.param(0)?);
+                let out_address = self.param_address(0)?;
+                self.output_register = Some(self.memory[out_address]);
+                if let Some(taint) = &mut self.taint {
+                    taint.output(out_address);
+                }
                 self.state = VMState::Blocked;
                 self.pc += 2;
             }
@@ -157,7 +253,12 @@ impl VirtualMachine {
     }
 
     fn jmp_condition(&mut self, cond: fn(MemoryValueType) -> bool) -> Result<()> {
-        if cond(self.param(0)?) {
+        let condition = self.param_address(0)?;
+        let taken = cond(self.memory[condition]);
+        if let Some(taint) = &mut self.taint {
+            taint.branch(self.pc, taken, condition);
+        }
+        if taken {
             let new_pc = self.param(1)?;
             if new_pc < 0 {
                 return Err(VMError::NegativeAddress);
@@ -198,7 +299,11 @@ impl VirtualMachine {
             return Err(VMError::ImmediateDestination);
         }
         let out_address = self.param_address(2)?;
-        self.memory[out_address] = f(self.param(0)?, self.param(1)?);
+        let sources = [self.param_address(0)?, self.param_address(1)?];
+        self.memory[out_address] = f(self.memory[sources[0]], self.memory[sources[1]]);
+        if let Some(taint) = &mut self.taint {
+            taint.propagate(&sources, out_address);
+        }
         self.pc += 4;
 
         Ok(())
@@ -215,6 +320,32 @@ impl Index<usize> for VirtualMachine {
 
 impl IndexMut<usize> for VirtualMachine {
     fn index_mut(&mut self, address: usize) -> &mut Self::Output {
+        if let Some(taint) = &mut self.taint {
+            taint.clear(address);
+        }
         &mut self.memory[address]
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume() {
+        // echoes one input
+        let mut vm = VirtualMachine::new(&[3,5, 4,5, 99, 0]).unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.state(), VMState::Blocked);
+        assert!(vm.step().is_err());
+        vm.input(7).unwrap();
+        vm.resume();
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.output().unwrap(), 7);
+        assert!(vm.step().is_err());
+        vm.resume();
+        vm.step().unwrap();
+        assert_eq!(vm.state(), VMState::Halted);
+    }
+}
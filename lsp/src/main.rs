@@ -0,0 +1,37 @@
+mod server;
+
+use log::{error, info};
+use protocol::{read_message, write_message};
+use server::Server;
+use std::io;
+
+fn main() {
+    pretty_env_logger::init();
+
+    info!("Serving the Language Server Protocol on stdio");
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut reader = stdin.lock();
+    let mut writer = stdout.lock();
+    let mut server = Server::new();
+    while !server.is_finished() {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(err) => {
+                error!("Could not read message: {}", err);
+                std::process::exit(1);
+            }
+        };
+        for message in server.handle(&message) {
+            if let Err(err) = write_message(&mut writer, &message) {
+                error!("Could not write message: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    // the protocol asks for an error code if the client did not shut down
+    if !server.is_shut_down() {
+        std::process::exit(1);
+    }
+}
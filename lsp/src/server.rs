@@ -0,0 +1,361 @@
+//! Request handling of the language server for `assembler` sources.
+//!
+//! Documents are synchronised in full on every change. Positions are
+//! counted in characters, which matches UTF-16 offsets for the ASCII sources
+//! the assembler accepts.
+
+use intcode_computer::assembler::{self, DIRECTIVES, MNEMONICS, PSEUDO_INSTRUCTIONS};
+use intcode_computer::opcode::Opcode;
+use log::debug;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const ERROR: i64 = 1;
+const KIND_KEYWORD: i64 = 14;
+const KIND_REFERENCE: i64 = 18;
+
+/// A word of the document with its line and character range.
+struct Word<'a> {
+    text: &'a str,
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
+pub struct Server {
+    documents: HashMap<String, String>,
+    shut_down: bool,
+    finished: bool,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server { documents: HashMap::new(), shut_down: false, finished: false }
+    }
+
+    /// True after the `exit` notification.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// True after the `shutdown` request.
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+    /// Handles a request or notification and returns the response, if any,
+    /// followed by notifications to the client.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        debug!("Message `{}`: {}", method, params);
+        let mut notifications = Vec::new();
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {"triggerCharacters": ["."]},
+                },
+                "serverInfo": {"name": "intcode-lsp"},
+            })),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "exit" => {
+                self.finished = true;
+                Ok(Value::Null)
+            }
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+                let text = if method == "textDocument/didOpen" {
+                    params["textDocument"]["text"].as_str()
+                } else {
+                    params["contentChanges"].as_array().and_then(|changes| changes.last()).and_then(|change| change["text"].as_str())
+                };
+                self.documents.insert(uri.clone(), text.unwrap_or("").to_string());
+                notifications.push(self.diagnostics(&uri));
+                Ok(Value::Null)
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                self.documents.remove(uri);
+                notifications.push(notification("textDocument/publishDiagnostics", json!({"uri": uri, "diagnostics": []})));
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => self.document(params).map(|(uri, text)| definition(uri, text, &params["position"])),
+            "textDocument/hover" => self.document(params).map(|(uri, text)| hover(uri, text, &params["position"])),
+            "textDocument/completion" => self.document(params).map(|(_, text)| completion(text, &params["position"])),
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method `{}`", method))),
+        };
+
+        // notifications do not get a response
+        let mut messages = Vec::new();
+        if !message["id"].is_null() {
+            let mut response = json!({"jsonrpc": "2.0", "id": message["id"]});
+            match result {
+                Ok(result) => response["result"] = result,
+                Err((code, message)) => response["error"] = json!({"code": code, "message": message}),
+            }
+            messages.push(response);
+        }
+        messages.append(&mut notifications);
+        messages
+    }
+
+    fn document<'a>(&'a self, params: &'a Value) -> Result<(&'a str, &'a str), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match self.documents.get(uri) {
+            Some(text) => Ok((uri, text)),
+            None => Err((INVALID_PARAMS, format!("Unknown document `{}`", uri))),
+        }
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = &self.documents[uri];
+        let diagnostics = match assembler::assemble(&module(uri), text) {
+            Ok(_) => Vec::new(),
+            Err(err) => {
+                let line = err.line() - 1;
+                let length = text.lines().nth(line).map_or(0, |line| line.chars().count());
+                vec![json!({
+                    "range": range(line, 0, length),
+                    "severity": ERROR,
+                    "source": "intcode",
+                    "message": err.to_string(),
+                })]
+            }
+        };
+        notification("textDocument/publishDiagnostics", json!({"uri": uri, "diagnostics": diagnostics}))
+    }
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+fn range(line: usize, start: usize, end: usize) -> Value {
+    json!({
+        "start": {"line": line, "character": start},
+        "end": {"line": line, "character": end},
+    })
+}
+
+/// Module name of a document, the file name without extension.
+fn module(uri: &str) -> String {
+    let name = uri.rsplit('/').next().unwrap_or(uri);
+    name.split('.').next().unwrap_or(name).to_string()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// The word touching the position.
+fn word_at<'a>(text: &'a str, position: &Value) -> Option<Word<'a>> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let content = text.lines().nth(line)?;
+    let chars: Vec<(usize, char)> = content.char_indices().collect();
+    let character = character.min(chars.len());
+    let start = chars[..character].iter().rposition(|&(_, c)| !is_word_char(c)).map_or(0, |i| i + 1);
+    let end = chars[character..].iter().position(|&(_, c)| !is_word_char(c)).map_or(chars.len(), |i| character + i);
+    if start == end {
+        return None;
+    }
+    let byte = |i: usize| chars.get(i).map_or(content.len(), |&(byte, _)| byte);
+    Some(Word { text: &content[byte(start)..byte(end)], line, start, end })
+}
+
+fn definition(uri: &str, text: &str, position: &Value) -> Value {
+    let word = match word_at(text, position) {
+        Some(word) => word,
+        None => return Value::Null,
+    };
+    match assembler::labels(text).into_iter().find(|(label, _)| label == word.text) {
+        Some((label, line)) => {
+            let content = text.lines().nth(line - 1).unwrap_or("");
+            let start = content.find(&format!("{}:", label)).map_or(0, |byte| content[..byte].chars().count());
+            json!({"uri": uri, "range": range(line - 1, start, start + label.chars().count())})
+        }
+        None => Value::Null,
+    }
+}
+
+fn directive_description(directive: &str) -> &'static str {
+    match directive {
+        ".global" => "Exports labels to other modules",
+        ".extern" => "Declares labels defined by other modules",
+        ".word" => "Places values into memory",
+        ".zero" => "Places the given number of zero words into memory",
+        ".file" => "Sets the source file of the following words",
+        ".loc" => "Sets the source line of the following words, `0` leaves them unmapped",
+        _ => "",
+    }
+}
+
+fn opcodes(opcodes: &[Opcode]) -> String {
+    opcodes.iter().map(Opcode::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn hover(uri: &str, text: &str, position: &Value) -> Value {
+    let word = match word_at(text, position) {
+        Some(word) => word,
+        None => return Value::Null,
+    };
+    let mnemonic = MNEMONICS.iter().find(|(name, _)| *name == word.text);
+    let pseudo = PSEUDO_INSTRUCTIONS.iter().find(|(name, _)| *name == word.text);
+    let mut contents = if let Some((name, opcode)) = mnemonic {
+        format!("**{}**: {} (opcode {})", name, opcode, *opcode as u8)
+    } else if let Some((name, expansion)) = pseudo {
+        format!("**{}**: pseudo instruction for {}", name, opcodes(expansion))
+    } else if DIRECTIVES.contains(&word.text) {
+        format!("**{}**: {}", word.text, directive_description(word.text))
+    } else if let Some((name, line)) = assembler::labels(text).into_iter().find(|(label, _)| label == word.text) {
+        format!("**{}**: label defined on line {}", name, line)
+    } else {
+        return Value::Null;
+    };
+
+    // the encoded words of instructions, relative to the start of the module
+    if mnemonic.is_some() || pseudo.is_some() {
+        let name = module(uri);
+        if let Ok(object) = assembler::assemble(&name, text) {
+            for addresses in object.source.addresses(&name, word.line + 1) {
+                let words: Vec<String> = object.code[addresses.clone()].iter().map(i64::to_string).collect();
+                contents.push_str(&format!("\n\n`{}` at offset {}", words.join(","), addresses.start));
+            }
+        }
+    }
+    json!({
+        "contents": {"kind": "markdown", "value": contents},
+        "range": range(word.line, word.start, word.end),
+    })
+}
+
+fn completion(text: &str, position: &Value) -> Value {
+    let directive = word_at(text, position).map_or(false, |word| word.text.starts_with('.'));
+    let mut items = Vec::new();
+    if directive {
+        for name in DIRECTIVES {
+            items.push(json!({"label": name, "kind": KIND_KEYWORD, "detail": directive_description(name)}));
+        }
+        return json!(items);
+    }
+    for (name, opcode) in MNEMONICS {
+        items.push(json!({"label": name, "kind": KIND_KEYWORD, "detail": opcode.to_string()}));
+    }
+    for (name, expansion) in PSEUDO_INSTRUCTIONS {
+        items.push(json!({"label": name, "kind": KIND_KEYWORD, "detail": format!("Pseudo instruction: {}", opcodes(expansion))}));
+    }
+    for (label, line) in assembler::labels(text) {
+        items.push(json!({"label": label, "kind": KIND_REFERENCE, "detail": format!("Label on line {}", line)}));
+    }
+    json!(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///tmp/echo.s";
+    const SOURCE: &str = "\
+.global start
+start:  in value
+        out value
+        push #1
+        jnz #1, #start
+value:  .word 0
+";
+
+    /// Sends the messages in order, requests are numbered and get the
+    /// method as id.
+    fn exchange(server: &mut Server, messages: &[(&str, Value)]) -> Vec<Value> {
+        let mut replies = Vec::new();
+        for (method, params) in messages {
+            let mut message = json!({"jsonrpc": "2.0", "method": method, "params": params});
+            if !method.contains("/did") && *method != "initialized" && *method != "exit" {
+                message["id"] = json!(method);
+            }
+            replies.extend(server.handle(&message));
+        }
+        replies
+    }
+
+    fn response<'a>(replies: &'a [Value], method: &str) -> &'a Value {
+        &replies.iter().find(|reply| reply["id"] == method).unwrap()["result"]
+    }
+
+    fn position(line: usize, character: usize) -> Value {
+        json!({"textDocument": {"uri": URI}, "position": {"line": line, "character": character}})
+    }
+
+    #[test]
+    fn test_session() {
+        let mut server = Server::new();
+        let replies = exchange(&mut server, &[
+            ("initialize", json!({"capabilities": {}})),
+            ("initialized", json!({})),
+            ("textDocument/didOpen", json!({"textDocument": {"uri": URI, "languageId": "intcode", "version": 1, "text": SOURCE}})),
+            ("textDocument/definition", position(4, 20)),
+            ("textDocument/hover", position(3, 9)),
+        ]);
+        assert!(response(&replies, "initialize")["capabilities"]["hoverProvider"].as_bool().unwrap());
+        assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+        assert_eq!(response(&replies, "textDocument/definition")["range"], range(1, 0, 5));
+        assert_eq!(response(&replies, "textDocument/hover")["contents"]["value"],
+                   "**push**: pseudo instruction for Addition, Relative base offset\n\n`21101,1,0,0,109,1` at offset 4");
+
+        let replies = exchange(&mut server, &[
+            ("textDocument/didChange", json!({
+                "textDocument": {"uri": URI, "version": 2},
+                "contentChanges": [{"text": SOURCE.replace("in value", "in #1")}],
+            })),
+            ("textDocument/hover", position(1, 9)),
+            ("shutdown", Value::Null),
+            ("exit", Value::Null),
+        ]);
+        let diagnostic = &replies[0]["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["range"], range(1, 0, 13));
+        assert!(diagnostic["message"].as_str().unwrap().contains("immediate"));
+        // without the encoded words as the document does not assemble
+        assert_eq!(response(&replies, "textDocument/hover")["contents"]["value"], "**in**: Input (opcode 3)");
+        assert!(server.is_shut_down() && server.is_finished());
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut server = Server::new();
+        let replies = exchange(&mut server, &[
+            ("textDocument/didOpen", json!({"textDocument": {"uri": URI, "text": "loop: jmp #l\n.w"}})),
+            ("textDocument/completion", position(0, 12)),
+        ]);
+        let labels = |replies: &[Value], method| -> Vec<String> {
+            response(replies, method).as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap().to_string()).collect()
+        };
+        let items = labels(&replies, "textDocument/completion");
+        assert!(items.contains(&"halt".to_string()) && items.contains(&"call".to_string()) && items.contains(&"loop".to_string()));
+
+        let replies = exchange(&mut server, &[("textDocument/completion", position(1, 2))]);
+        assert_eq!(labels(&replies, "textDocument/completion"), DIRECTIVES.to_vec());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut server = Server::new();
+        let replies = exchange(&mut server, &[
+            ("textDocument/hover", position(0, 0)),
+            ("textDocument/formatting", json!({})),
+            ("textDocument/didSave", json!({})),
+        ]);
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(replies[1]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,90 @@
+//! Framing shared by the Debug Adapter and the Language Server Protocol:
+//! a `Content-Length` header, an empty line and a JSON body.
+
+use serde_json::Value;
+use std::io::{self, BufRead, Read, Write};
+
+/// Largest body that is read, the body of a longer message is skipped.
+pub const MAX_LENGTH: usize = 4 * 1024 * 1024;
+
+/// Reads the next message, `None` at the end of the stream. A message that
+/// is too long or not valid JSON is an `InvalidData` error, reading again
+/// continues with the next message.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            let value = value.parse::<usize>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length"))?;
+            length = Some(value);
+        }
+    }
+    let length = length.unwrap_or(0);
+    if length > MAX_LENGTH {
+        io::copy(&mut reader.by_ref().take(length as u64), &mut io::sink())?;
+        let message = format!("Content-Length {} is larger than {}", length, MAX_LENGTH);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip() {
+        let messages = vec![
+            json!({"seq": 1, "type": "request", "command": "initialize"}),
+            json!({"seq": 2, "type": "request", "command": "threads", "arguments": {"text": "ä\r\n"}}),
+        ];
+        let mut buffer = Vec::new();
+        for message in &messages {
+            write_message(&mut buffer, message).unwrap();
+        }
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(read_message(&mut reader).unwrap().as_ref(), Some(&messages[0]));
+        assert_eq!(read_message(&mut reader).unwrap().as_ref(), Some(&messages[1]));
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_too_long() {
+        let mut buffer = format!("Content-Length: {}\r\n\r\n", MAX_LENGTH + 1).into_bytes();
+        buffer.resize(buffer.len() + MAX_LENGTH + 1, b' ');
+        write_message(&mut buffer, &json!({"seq": 1})).unwrap();
+        buffer.extend_from_slice(b"Content-Length: 99999999999999\r\n\r\n{}");
+
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(read_message(&mut reader).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({"seq": 1})));
+        assert_eq!(read_message(&mut reader).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
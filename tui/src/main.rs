@@ -0,0 +1,153 @@
+mod session;
+mod view;
+
+use intcode_computer::loader::load_file;
+use intcode_computer::memory::MemoryValueType;
+use log::error;
+use session::{Access, Session};
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+use termion::{clear, color, cursor, style};
+use view::{Line, Style};
+
+const FRAME: Duration = Duration::from_millis(33);
+const MAX_SPEED: usize = 1_000_000;
+
+struct Options {
+    path: String,
+    inputs: Vec<MemoryValueType>,
+    fill: Option<MemoryValueType>,
+}
+
+const USAGE: &str = "usage: tui <program> [--input <values>] [--fill <value>]";
+
+fn values(s: &str) -> Result<Vec<MemoryValueType>, String> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse().map_err(|_| format!("Invalid value `{}`", value)))
+        .collect()
+}
+
+fn options() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut options = Options { path: String::new(), inputs: Vec::new(), fill: None };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => options.inputs.extend(values(&args.next().ok_or(USAGE)?)?),
+            "--fill" => options.fill = Some(values(&args.next().ok_or(USAGE)?)?.first().cloned().ok_or(USAGE)?),
+            _ if options.path.is_empty() => options.path = arg,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    if options.path.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    Ok(options)
+}
+
+fn draw<W: Write>(out: &mut W, lines: &[Line]) -> io::Result<()> {
+    for (row, line) in lines.iter().enumerate() {
+        write!(out, "{}", cursor::Goto(1, row as u16 + 1))?;
+        for span in line {
+            match span.style {
+                Style::Normal => write!(out, "{}", span.text)?,
+                Style::Title => write!(out, "{}{}{}", style::Bold, span.text, style::Reset)?,
+                Style::Current => write!(out, "{}{}{}", style::Invert, span.text, style::Reset)?,
+                Style::Status => write!(out, "{}{}{}", color::Fg(color::Yellow), span.text, style::Reset)?,
+                Style::Heat(access, level) => {
+                    let shades = match access {
+                        Access::Read => [24, 31, 38, 45],
+                        Access::Write => [88, 124, 160, 196],
+                    };
+                    let shade = color::AnsiValue(shades[level as usize - 1]);
+                    write!(out, "{}{}{}", color::Fg(shade), span.text, style::Reset)?;
+                }
+            }
+        }
+        write!(out, "{}", clear::UntilNewline)?;
+    }
+    out.flush()
+}
+
+fn run(session: &mut Session, program: usize) -> io::Result<()> {
+    let stdout = io::stdout().into_raw_mode()?;
+    let mut screen = AlternateScreen::from(stdout);
+    write!(screen, "{}{}", cursor::Hide, clear::All)?;
+    let mut keys = termion::async_stdin().keys();
+    let mut prompt: Option<String> = None;
+
+    loop {
+        for key in &mut keys {
+            match (key?, &mut prompt) {
+                (Key::Char('\n'), Some(text)) => {
+                    match values(text) {
+                        Ok(values) => values.into_iter().for_each(|value| session.provide_input(value)),
+                        Err(err) => session.status = Some(err),
+                    }
+                    prompt = None;
+                }
+                (Key::Esc, Some(_)) => prompt = None,
+                (Key::Backspace, Some(text)) => {
+                    text.pop();
+                }
+                (Key::Char(c), Some(text)) => text.push(c),
+                (Key::Char('q'), None) | (Key::Ctrl('c'), _) => {
+                    write!(screen, "{}", cursor::Show)?;
+                    return Ok(());
+                }
+                (Key::Char(' '), None) => session.playing = !session.playing,
+                (Key::Char('s'), None) | (Key::Right, None) => {
+                    session.playing = false;
+                    session.step();
+                }
+                (Key::Char('+'), None) => session.speed = (session.speed * 10).min(MAX_SPEED),
+                (Key::Char('-'), None) => session.speed = (session.speed / 10).max(1),
+                (Key::Char('i'), None) => prompt = Some(String::new()),
+                _ => (),
+            }
+        }
+
+        session.tick();
+        let (width, height) = termion::terminal_size()?;
+        let lines = view::render(session, program, prompt.as_deref(), width as usize, height as usize);
+        draw(&mut screen, &lines)?;
+        thread::sleep(FRAME);
+    }
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let options = match options() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    let program = match load_file(&options.path) {
+        Ok(program) => program,
+        Err(err) => {
+            error!("Could not load program: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let mut session = match Session::new(&program, &options.inputs) {
+        Ok(session) => session,
+        Err(err) => {
+            error!("Could not start program: {}", err);
+            std::process::exit(1);
+        }
+    };
+    session.fill = options.fill;
+
+    if let Err(err) = run(&mut session, program.len()) {
+        error!("Terminal error: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,155 @@
+//! A running program together with the statistics the views show.
+
+use intcode_computer::debugger::{Debugger, Mode, Stop};
+use intcode_computer::error::VMError;
+use intcode_computer::memory::MemoryValueType;
+use intcode_computer::opcode::{Opcode, decode_opcode};
+use intcode_computer::sourcemap::DebugInfo;
+use intcode_computer::virtual_machine::VMState;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+pub struct Session {
+    pub debugger: Debugger,
+    /// Answer to every read when no input is queued
+    pub fill: Option<MemoryValueType>,
+    /// Executed instructions
+    pub steps: u64,
+    /// Number of executions per instruction address
+    pub executions: HashMap<usize, u64>,
+    /// Step and kind of the last access per address
+    pub accesses: HashMap<usize, (u64, Access)>,
+    pub inputs: Vec<MemoryValueType>,
+    pub outputs: Vec<MemoryValueType>,
+    pub playing: bool,
+    /// Instructions per frame while playing
+    pub speed: usize,
+    /// Why the program does not continue, if it cannot
+    pub status: Option<String>,
+}
+
+impl Session {
+    pub fn new(program: &[MemoryValueType], inputs: &[MemoryValueType]) -> Result<Session, VMError> {
+        let mut debugger = Debugger::new(program, DebugInfo::default())?;
+        for &input in inputs {
+            debugger.provide_input(input);
+        }
+        Ok(Session {
+            debugger,
+            fill: None,
+            steps: 0,
+            executions: HashMap::new(),
+            accesses: HashMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            playing: false,
+            speed: 1,
+            status: None,
+        })
+    }
+
+    pub fn provide_input(&mut self, value: MemoryValueType) {
+        self.debugger.provide_input(value);
+        self.status = None;
+    }
+
+    /// Executes one instruction, returns false if the machine cannot go on.
+    pub fn step(&mut self) -> bool {
+        let vm = self.debugger.vm();
+        if vm.state() == VMState::Halted {
+            return false;
+        }
+        let pc = vm.pc();
+        let accesses = match vm.next_accesses() {
+            Ok(accesses) => accesses,
+            Err(err) => return self.stop(format!("Error: {}", err)),
+        };
+        let reads = decode_opcode(vm[pc]).ok() == Some(Opcode::In);
+        if reads && self.debugger.pending_inputs().next().is_none() {
+            if let Some(fill) = self.fill {
+                self.debugger.provide_input(fill);
+            }
+        }
+        let input = self.debugger.pending_inputs().next().cloned();
+
+        self.debugger.start(Mode::Instruction);
+        let stop = match self.debugger.run(1) {
+            Ok(stop) => stop,
+            Err(err) => return self.stop(format!("Error: {}", err)),
+        };
+        if stop == Some(Stop::Input) {
+            return self.stop("Waiting for input".to_string());
+        }
+
+        self.steps += 1;
+        *self.executions.entry(pc).or_insert(0) += 1;
+        for &address in accesses.reads.iter().chain(&accesses.operands) {
+            self.accesses.insert(address, (self.steps, Access::Read));
+        }
+        if let Some(address) = accesses.write {
+            self.accesses.insert(address, (self.steps, Access::Write));
+        }
+        if reads {
+            self.inputs.extend(input);
+        }
+        self.outputs.extend(self.debugger.take_outputs());
+        if stop == Some(Stop::Halted) {
+            return self.stop("Halted".to_string());
+        }
+        self.status = None;
+        true
+    }
+
+    /// Executes up to `speed` instructions if playing.
+    pub fn tick(&mut self) {
+        for _ in 0..self.speed {
+            if !self.playing {
+                break;
+            }
+            if !self.step() {
+                self.playing = false;
+            }
+        }
+    }
+
+    fn stop(&mut self, status: String) -> bool {
+        self.status = Some(status);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics() {
+        // adds up inputs until it reads a zero
+        let program = [3,15, 1006,15,12, 1,15,16,16, 1105,1,0, 4,16, 99, 0,0];
+        let mut session = Session::new(&program, &[5, 6]).unwrap();
+        session.playing = true;
+        session.speed = 100;
+        session.tick();
+        assert!(!session.playing);
+        assert_eq!(session.status.as_deref(), Some("Waiting for input"));
+        assert_eq!(session.inputs, vec![5, 6]);
+        assert_eq!(session.executions[&0], 2);
+        assert_eq!(session.executions[&5], 2);
+        assert_eq!(session.accesses[&16].1, Access::Write);
+        assert_eq!(session.accesses[&15].1, Access::Read);
+
+        session.fill = Some(0);
+        assert!(session.step());
+        assert!(session.status.is_none());
+        while session.step() {}
+        assert_eq!(session.status.as_deref(), Some("Halted"));
+        assert_eq!(session.inputs, vec![5, 6, 0]);
+        assert_eq!(session.outputs, vec![11]);
+        assert!(!session.step());
+    }
+}
@@ -0,0 +1,248 @@
+//! Layout of the screen as styled lines, independent of the terminal.
+
+use crate::session::{Access, Session};
+use intcode_computer::cfg::Instruction;
+use intcode_computer::memory::MemoryValueType;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Style {
+    Normal,
+    Title,
+    /// The instruction at `pc`
+    Current,
+    /// Memory accessed recently, the level grows with recency from 1 to 4
+    Heat(Access, u8),
+    Status,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+pub type Line = Vec<Span>;
+
+const HEAT: [char; 5] = [' ', '.', ':', '*', '#'];
+const HELP: &str = "space play/pause  s step  +/- speed  i input  q quit";
+
+fn span(text: String, style: Style) -> Span {
+    Span { text, style }
+}
+
+/// Pads or truncates the line to exactly `width` characters.
+fn fit(mut line: Line, width: usize) -> Line {
+    let mut left = width;
+    for span in &mut line {
+        let length = span.text.chars().count();
+        if length > left {
+            span.text = span.text.chars().take(left).collect();
+        }
+        left -= span.text.chars().count();
+    }
+    line.retain(|span| !span.text.is_empty());
+    if left > 0 {
+        line.push(span(" ".repeat(left), Style::Normal));
+    }
+    line
+}
+
+/// Instructions around `pc`: those executed before it and the ones decoded
+/// straight from `pc` onwards. Decoding backwards is ambiguous, so only
+/// addresses known to be instructions are shown above.
+fn disassembly(session: &Session, rows: usize) -> Vec<Line> {
+    let pc = session.debugger.vm().pc();
+    let mut above: Vec<usize> = session.executions.keys().cloned().filter(|&address| address < pc).collect();
+    above.sort();
+    let skip = above.len().saturating_sub(rows / 3);
+
+    let mut addresses: Vec<usize> = above[skip..].to_vec();
+    let mut address = pc;
+    while addresses.len() < rows {
+        addresses.push(address);
+        address += decode(session, address).map_or(1, |instruction| instruction.length());
+    }
+
+    let mut lines = vec![vec![span("Disassembly".to_string(), Style::Title)]];
+    for address in addresses.into_iter().take(rows.saturating_sub(1)) {
+        let text = match decode(session, address) {
+            Some(instruction) => instruction.to_string(),
+            None => format!(".word {}", session.debugger.vm()[address]),
+        };
+        let count = session.executions.get(&address).map_or(String::new(), u64::to_string);
+        let marker = if address == pc { '>' } else { ' ' };
+        let style = if address == pc { Style::Current } else { Style::Normal };
+        lines.push(vec![span(format!("{}{:>6} {:>9}  {}", marker, address, count, text), style)]);
+    }
+    lines
+}
+
+fn decode(session: &Session, address: usize) -> Option<Instruction> {
+    let words = session.debugger.memory(address..address + 4);
+    let mut instruction = Instruction::decode(&words, 0).ok()?;
+    instruction.address = address;
+    Some(instruction)
+}
+
+fn registers(session: &Session) -> Vec<Line> {
+    let vm = session.debugger.vm();
+    let register = |value: Option<MemoryValueType>| value.map_or("-".to_string(), |value| value.to_string());
+    let playing = if session.playing { "playing" } else { "paused" };
+    let rows = vec![
+        ("pc", vm.pc().to_string()),
+        ("rb", vm.relative_base().to_string()),
+        ("state", format!("{:?}", vm.state())),
+        ("input", register(vm.input_register())),
+        ("output", register(vm.output_register())),
+        ("steps", session.steps.to_string()),
+        ("speed", format!("{} per frame, {}", session.speed, playing)),
+    ];
+    let mut lines = vec![vec![span("Registers".to_string(), Style::Title)]];
+    lines.extend(rows.into_iter().map(|(name, value)| vec![span(format!("{:<7} {}", name, value), Style::Normal)]));
+    if let Some(status) = &session.status {
+        lines.push(vec![span(status.clone(), Style::Status)]);
+    }
+    lines
+}
+
+/// Memory from address 0 up to the highest used one, every cell stands for
+/// a block of addresses and shows its most recent access.
+fn heat_map(session: &Session, program: usize, width: usize, rows: usize) -> Vec<Line> {
+    let extent = session.accesses.keys().map(|&address| address + 1).max().unwrap_or(0).max(program).max(1);
+    let cells = (width * rows.saturating_sub(1)).max(1);
+    let block = (extent + cells - 1) / cells;
+
+    let mut latest: Vec<Option<(u64, Access)>> = vec![None; (extent + block - 1) / block];
+    for (&address, &(step, access)) in &session.accesses {
+        let cell = &mut latest[address / block];
+        if cell.map_or(true, |(latest, _)| step > latest) {
+            *cell = Some((step, access));
+        }
+    }
+
+    let title = format!("Memory 0..{}, {} per cell", extent, block);
+    let mut lines = vec![vec![span(title, Style::Title)]];
+    for row in latest.chunks(width.max(1)) {
+        let line = row.iter()
+            .map(|cell| match cell {
+                Some((step, access)) => {
+                    let level = match session.steps - step {
+                        0..=9 => 4,
+                        10..=99 => 3,
+                        100..=9999 => 2,
+                        _ => 1,
+                    };
+                    span(HEAT[level as usize].to_string(), Style::Heat(*access, level))
+                }
+                None => span(" ".to_string(), Style::Normal),
+            })
+            .collect();
+        lines.push(line);
+    }
+    lines
+}
+
+/// The most recent values that fit into the width.
+fn stream(name: &str, values: &[MemoryValueType], width: usize) -> Line {
+    let mut text = String::new();
+    for value in values.iter().rev() {
+        let value = format!(" {}", value);
+        if name.len() + text.len() + value.len() > width {
+            break;
+        }
+        text.insert_str(0, &value);
+    }
+    vec![span(name.to_string(), Style::Title), span(text, Style::Normal)]
+}
+
+/// Instructions with the largest share of all executed steps.
+fn hot_spots(session: &Session, width: usize) -> Line {
+    let mut counts: Vec<(&usize, &u64)> = session.executions.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let mut text = String::new();
+    for (address, count) in counts {
+        let entry = format!(" {} ({}%)", address, count * 100 / session.steps.max(1));
+        if "hot:".len() + text.len() + entry.len() > width {
+            break;
+        }
+        text.push_str(&entry);
+    }
+    vec![span("hot:".to_string(), Style::Title), span(text, Style::Normal)]
+}
+
+/// The whole screen, exactly `height` lines of `width` characters. `prompt`
+/// is an input being typed, it replaces the help line.
+pub fn render(session: &Session, program: usize, prompt: Option<&str>, width: usize, height: usize) -> Vec<Line> {
+    let rows = height.saturating_sub(4);
+    let left = (width / 2).max(1);
+    let right = width.saturating_sub(left + 1);
+
+    let code = disassembly(session, rows);
+    let mut side = registers(session);
+    side.push(Vec::new());
+    let map_rows = rows.saturating_sub(side.len());
+    side.extend(heat_map(session, program, right, map_rows));
+
+    let mut lines = Vec::with_capacity(height);
+    for row in 0..rows {
+        let mut line = fit(code.get(row).cloned().unwrap_or_default(), left);
+        line.push(span("│".to_string(), Style::Normal));
+        line.extend(fit(side.get(row).cloned().unwrap_or_default(), right));
+        lines.push(line);
+    }
+    lines.push(fit(stream("in:", &session.inputs, width), width));
+    lines.push(fit(stream("out:", &session.outputs, width), width));
+    lines.push(fit(hot_spots(session, width), width));
+    let last = match prompt {
+        Some(input) => vec![span(format!("input> {}_", input), Style::Status)],
+        None => vec![span(HELP.to_string(), Style::Title)],
+    };
+    lines.push(fit(last, width));
+    lines.truncate(height);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &Line) -> String {
+        line.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_render() {
+        // counts down from 3 and outputs every value
+        let program = [1101,3,0,20, 4,20, 1001,20,-1,20, 1005,20,4, 99];
+        let mut session = Session::new(&program, &[]).unwrap();
+        for _ in 0..7 {
+            assert!(session.step());
+        }
+        let lines = render(&session, program.len(), None, 80, 20);
+        assert_eq!(lines.len(), 20);
+        assert!(lines.iter().all(|line| text(line).chars().count() == 80));
+
+        let code: Vec<String> = lines.iter().map(|line| text(line)[..40].trim_end().to_string()).collect();
+        assert_eq!(code[1], "      0         1  add #3, #0, 20");
+        assert_eq!(code[2], ">     4         2  out 20");
+        assert_eq!(lines[2][0].style, Style::Current);
+        assert_eq!(code[3], "      6         2  add 20, #-1, 20");
+        assert_eq!(code[4], "     10         2  jnz 20, #4");
+        assert_eq!(code[5], "     13            halt");
+
+        assert!(text(&lines[16]).starts_with("in: "));
+        assert!(text(&lines[17]).starts_with("out: 3 2 "));
+        assert!(text(&lines[18]).starts_with("hot: 4 (28%) 6 (28%) 10 (28%) 0 (14%) "));
+        assert!(text(&lines[19]).starts_with(HELP));
+
+        // the jump just read the counter
+        let map = lines.iter().position(|line| text(line).contains("Memory 0..21, 1 per cell")).unwrap();
+        let cells: Vec<&Span> = lines[map + 1].iter().skip_while(|span| span.text != "│").skip(1).collect();
+        assert_eq!(cells[20].style, Style::Heat(Access::Read, 4));
+        assert_eq!(cells[20].text, "#");
+        assert_eq!(cells[15].style, Style::Normal);
+
+        let lines = render(&session, program.len(), Some("12"), 30, 6);
+        assert_eq!(text(&lines[5]).trim_end(), "input> 12_");
+    }
+}